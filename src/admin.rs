@@ -0,0 +1,160 @@
+// administrative boundaries and the point-in-polygon lookups against them
+use crate::geometry::{Coord, Polygon};
+use rstar::{RTree, RTreeObject, AABB};
+use std::collections::HashMap;
+
+// admin_level values as used in Canada, see
+// https://wiki.openstreetmap.org/wiki/Tag:boundary%3Dadministrative#Country_specific_values
+const PROVINCE_LEVEL: u8 = 4;
+const COUNTY_LEVELS: std::ops::RangeInclusive<u8> = 5..=6;
+const MUNICIPALITY_LEVELS: std::ops::RangeInclusive<u8> = 7..=8;
+
+// a boundary=administrative relation assembled into polygons
+pub struct AdminArea {
+    pub id: i64,
+    pub name: String,
+    pub admin_level: u8,
+    pub polygons: Vec<Polygon>,
+}
+
+// the areas a point falls in, empty when there is no boundary for that level
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AdminNames {
+    pub municipality: String,
+    pub county: String,
+    pub province: String,
+}
+
+// admin level of a boundary relation, None for everything else
+pub fn admin_level(tags: &HashMap<String, String>) -> Option<u8> {
+    if tags.get("boundary").map(String::as_str) != Some("administrative") {
+        return None;
+    }
+    tags.get("admin_level")?.trim().parse().ok()
+}
+
+// one ring edge, points are [lon, lat] since containment doesn't depend on distances
+struct Edge {
+    from: [f64; 2],
+    to: [f64; 2],
+}
+
+impl RTreeObject for Edge {
+    type Envelope = AABB<[f64; 2]>;
+
+    fn envelope(&self) -> Self::Envelope {
+        AABB::from_corners(self.from, self.to)
+    }
+}
+
+// boundaries can have hundreds of thousands of vertices, so each area keeps its edges in
+// their own tree and a containment test only looks at the edges level with the point
+struct IndexedArea {
+    id: i64,
+    name: String,
+    admin_level: u8,
+    envelope: AABB<[f64; 2]>,
+    edges: RTree<Edge>,
+}
+
+impl IndexedArea {
+    fn new(area: AdminArea) -> Option<IndexedArea> {
+        let rings = area
+            .polygons
+            .iter()
+            .flat_map(|p| std::iter::once(&p.outer).chain(p.holes.iter()));
+        let edges: Vec<Edge> = rings
+            .flat_map(|ring| ring.windows(2))
+            .map(|pair| Edge {
+                from: lon_lat(pair[0]),
+                to: lon_lat(pair[1]),
+            })
+            .collect();
+        if edges.is_empty() {
+            return None;
+        }
+
+        let corners: Vec<[f64; 2]> = edges.iter().flat_map(|e| [e.from, e.to]).collect();
+        Some(IndexedArea {
+            id: area.id,
+            name: area.name,
+            admin_level: area.admin_level,
+            envelope: AABB::from_points(&corners),
+            edges: RTree::bulk_load(edges),
+        })
+    }
+
+    // even-odd ray cast towards the east, holes and separate outer rings need no special
+    // handling since crossing any ring flips the parity
+    fn contains(&self, [lon, lat]: [f64; 2]) -> bool {
+        let ray = AABB::from_corners([lon, lat], [self.envelope.upper()[0], lat]);
+        self.edges
+            .locate_in_envelope_intersecting(&ray)
+            .filter(|e| {
+                let ([lon1, lat1], [lon2, lat2]) = (e.from, e.to);
+                (lat1 > lat) != (lat2 > lat)
+                    && lon < (lon2 - lon1) * (lat - lat1) / (lat2 - lat1) + lon1
+            })
+            .count()
+            % 2
+            == 1
+    }
+}
+
+impl RTreeObject for IndexedArea {
+    type Envelope = AABB<[f64; 2]>;
+
+    fn envelope(&self) -> Self::Envelope {
+        self.envelope
+    }
+}
+
+pub struct AdminIndex {
+    areas: RTree<IndexedArea>,
+}
+
+impl AdminIndex {
+    pub fn new(areas: Vec<AdminArea>) -> AdminIndex {
+        AdminIndex {
+            areas: RTree::bulk_load(areas.into_iter().filter_map(IndexedArea::new).collect()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.areas.size()
+    }
+
+    pub fn lookup(&self, lat: f64, lon: f64) -> AdminNames {
+        let point = [lon, lat];
+        // the most detailed level wins inside each slot, ties go to the lowest relation id
+        let mut best: [Option<&IndexedArea>; 3] = [None, None, None];
+
+        let at_point = AABB::from_point(point);
+        for area in self.areas.locate_in_envelope_intersecting(&at_point) {
+            let slot = match area.admin_level {
+                PROVINCE_LEVEL => 2,
+                level if COUNTY_LEVELS.contains(&level) => 1,
+                level if MUNICIPALITY_LEVELS.contains(&level) => 0,
+                _ => continue,
+            };
+            let better = best[slot].is_none_or(|current| {
+                (area.admin_level, std::cmp::Reverse(area.id))
+                    > (current.admin_level, std::cmp::Reverse(current.id))
+            });
+            if better && area.contains(point) {
+                best[slot] = Some(area);
+            }
+        }
+
+        let name = |slot: usize| best[slot].map(|a| a.name.clone()).unwrap_or_default();
+        AdminNames {
+            municipality: name(0),
+            county: name(1),
+            province: name(2),
+        }
+    }
+}
+
+fn lon_lat((lat, lon): Coord) -> [f64; 2] {
+    [lon, lat]
+}
@@ -0,0 +1,340 @@
+// category mapping rules, the built-in table lives in categories.toml
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use toml::Spanned;
+
+const BUILTIN_CATEGORIES: &str = include_str!("categories.toml");
+const WILDCARD: &str = "*";
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawMapping {
+    key_precedence: Option<Spanned<Vec<String>>>,
+    #[serde(default)]
+    rule: Vec<Spanned<RawRule>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRule {
+    category: Spanned<String>,
+    keys: Spanned<Vec<String>>,
+    values: Spanned<Vec<String>>,
+    subcategory: Option<Spanned<String>>,
+    #[serde(default)]
+    priority: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct CategoryRule {
+    pub category: String,
+    pub keys: Vec<String>,
+    pub values: HashSet<String>,
+    pub subcategory: Option<String>,
+    pub priority: i64,
+}
+
+impl CategoryRule {
+    // every (key, value) of the element this rule accepts, in the rule's key order
+    fn matches<'a>(
+        &'a self,
+        tags: &'a HashMap<String, String>,
+    ) -> impl Iterator<Item = (&'a str, &'a str)> + 'a {
+        self.keys.iter().filter_map(move |key| {
+            let value = tags.get(key)?;
+            if self.values.contains(WILDCARD) || self.values.contains(value) {
+                Some((key.as_str(), value.as_str()))
+            } else {
+                None
+            }
+        })
+    }
+}
+
+// one category an element qualifies for, the first match of a poi is its primary category
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct CategoryMatch {
+    pub category: String,
+    pub subcategory: String,
+    pub tag_key: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct CategoryMapping {
+    // in file order, match ordering is worked out in classify
+    rules: Vec<CategoryRule>,
+    key_precedence: Vec<String>,
+}
+
+impl CategoryMapping {
+    pub fn builtin() -> Self {
+        Self::parse(BUILTIN_CATEGORIES, "categories.toml")
+            .expect("built-in categories.toml is invalid")
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let src = fs::read_to_string(path)
+            .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        Self::parse(&src, &path.display().to_string())
+    }
+
+    pub fn parse(src: &str, origin: &str) -> Result<Self, String> {
+        let raw: RawMapping = toml::from_str(src).map_err(|e| {
+            let line = e.span().map(|span| line_of(src, span.start)).unwrap_or(1);
+            format!("{}:{}: {}", origin, line, e.message())
+        })?;
+
+        let error_at = |span: Range<usize>, msg: &str| {
+            format!("{}:{}: {}", origin, line_of(src, span.start), msg)
+        };
+
+        if raw.rule.is_empty() {
+            return Err(format!("{}: no [[rule]] entries found", origin));
+        }
+
+        let key_precedence = match raw.key_precedence {
+            Some(keys) => {
+                if let Err(msg) = check_precedence(keys.get_ref()) {
+                    return Err(error_at(keys.span(), &msg));
+                }
+                keys.into_inner()
+            }
+            None => Vec::new(),
+        };
+
+        let mut rules = Vec::with_capacity(raw.rule.len());
+        for spanned_rule in raw.rule {
+            let rule = spanned_rule.into_inner();
+
+            if rule.category.get_ref().trim().is_empty() {
+                return Err(error_at(rule.category.span(), "category must not be empty"));
+            }
+            if rule.keys.get_ref().is_empty() {
+                return Err(error_at(rule.keys.span(), "rule needs at least one key"));
+            }
+            if rule.keys.get_ref().iter().any(|k| k.trim().is_empty()) {
+                return Err(error_at(rule.keys.span(), "keys must not be empty strings"));
+            }
+            if rule.values.get_ref().is_empty() {
+                return Err(error_at(
+                    rule.values.span(),
+                    "rule needs at least one value, use \"*\" to accept any value",
+                ));
+            }
+            if rule.values.get_ref().iter().any(|v| v.is_empty()) {
+                return Err(error_at(
+                    rule.values.span(),
+                    "values must not be empty strings",
+                ));
+            }
+            if let Some(sub) = &rule.subcategory {
+                if sub.get_ref().trim().is_empty() {
+                    return Err(error_at(sub.span(), "subcategory must not be empty"));
+                }
+            }
+
+            rules.push(CategoryRule {
+                category: rule.category.into_inner(),
+                keys: rule.keys.into_inner(),
+                values: rule.values.into_inner().into_iter().collect(),
+                subcategory: rule.subcategory.map(Spanned::into_inner),
+                priority: rule.priority,
+            });
+        }
+
+        Ok(CategoryMapping {
+            rules,
+            key_precedence,
+        })
+    }
+
+    pub fn rules(&self) -> &[CategoryRule] {
+        &self.rules
+    }
+
+    pub fn key_precedence(&self) -> &[String] {
+        &self.key_precedence
+    }
+
+    // replaces the file's key_precedence, used by the --precedence flag
+    pub fn set_key_precedence(&mut self, keys: Vec<String>) -> Result<(), String> {
+        check_precedence(&keys)?;
+        self.key_precedence = keys;
+        Ok(())
+    }
+
+    pub fn is_match(&self, tags: &HashMap<String, String>) -> bool {
+        self.rules
+            .iter()
+            .any(|rule| rule.matches(tags).next().is_some())
+    }
+
+    // every category the element's tags qualify for, best match first
+    // ordered by rule priority, then key precedence, then file order so the
+    // result never depends on hash iteration order
+    pub fn classify(&self, tags: &HashMap<String, String>) -> Vec<CategoryMatch> {
+        let mut ranked = Vec::new();
+
+        for (rule_idx, rule) in self.rules.iter().enumerate() {
+            for (key, value) in rule.matches(tags) {
+                let key_rank = self
+                    .key_precedence
+                    .iter()
+                    .position(|k| k == key)
+                    .unwrap_or(self.key_precedence.len());
+                let found = CategoryMatch {
+                    category: rule.category.clone(),
+                    subcategory: rule.subcategory.as_deref().unwrap_or(value).to_string(),
+                    tag_key: key.to_string(),
+                };
+                if !ranked.iter().any(|(_, m)| *m == found) {
+                    ranked.push((
+                        (std::cmp::Reverse(rule.priority), key_rank, rule_idx),
+                        found,
+                    ));
+                }
+            }
+        }
+
+        // stable, so keys within one rule keep their listed order
+        ranked.sort_by_key(|(rank, _)| *rank);
+        ranked.into_iter().map(|(_, m)| m).collect()
+    }
+}
+
+fn check_precedence(keys: &[String]) -> Result<(), String> {
+    let mut seen = HashSet::new();
+    for key in keys {
+        if key.trim().is_empty() {
+            return Err("key_precedence must not contain empty keys".to_string());
+        }
+        if !seen.insert(key.as_str()) {
+            return Err(format!("key_precedence lists \"{}\" more than once", key));
+        }
+    }
+    Ok(())
+}
+
+fn line_of(src: &str, offset: usize) -> usize {
+    src[..offset.min(src.len())].matches('\n').count() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn found(category: &str, subcategory: &str, tag_key: &str) -> CategoryMatch {
+        CategoryMatch {
+            category: category.to_string(),
+            subcategory: subcategory.to_string(),
+            tag_key: tag_key.to_string(),
+        }
+    }
+
+    fn cafe_attraction() -> HashMap<String, String> {
+        tags(&[("tourism", "attraction"), ("amenity", "cafe")])
+    }
+
+    #[test]
+    fn key_precedence_picks_the_primary_category() {
+        let mapping = CategoryMapping::builtin();
+        assert_eq!(
+            mapping.classify(&cafe_attraction()),
+            [
+                found("food", "cafe", "amenity"),
+                found("entertainment", "attraction", "tourism"),
+            ]
+        );
+    }
+
+    #[test]
+    fn precedence_flag_reorders_matches() {
+        let mut mapping = CategoryMapping::builtin();
+        mapping
+            .set_key_precedence(vec!["tourism".to_string(), "amenity".to_string()])
+            .unwrap();
+        assert_eq!(
+            mapping.classify(&cafe_attraction()),
+            [
+                found("entertainment", "attraction", "tourism"),
+                found("food", "cafe", "amenity"),
+            ]
+        );
+    }
+
+    #[test]
+    fn priority_beats_key_precedence() {
+        let mut mapping = CategoryMapping::builtin();
+        let attraction = mapping
+            .rules
+            .iter_mut()
+            .find(|r| r.keys == ["tourism"] && r.values.contains("attraction"))
+            .unwrap();
+        attraction.priority = 1;
+        assert_eq!(
+            mapping.classify(&cafe_attraction())[0],
+            found("entertainment", "attraction", "tourism")
+        );
+    }
+
+    // parse errors start with origin:line so they point at the mistake
+    fn parse_error(src: &str) -> String {
+        CategoryMapping::parse(src, "my.toml").unwrap_err()
+    }
+
+    #[test]
+    fn empty_values_are_reported_at_their_line() {
+        let src = r#"
+[[rule]]
+category = "food"
+keys = ["amenity"]
+values = ["cafe"]
+
+[[rule]]
+category = "shop"
+keys = ["shop"]
+values = []
+"#;
+        let error = parse_error(src);
+        assert!(error.starts_with("my.toml:10: "), "{}", error);
+        assert!(error.contains("at least one value"), "{}", error);
+    }
+
+    #[test]
+    fn unknown_fields_are_reported_at_their_line() {
+        let src = r#"
+[[rule]]
+category = "food"
+keys = ["amenity"]
+valeus = ["cafe"]
+"#;
+        let error = parse_error(src);
+        assert!(error.starts_with("my.toml:5: "), "{}", error);
+        assert!(error.contains("unknown field `valeus`"), "{}", error);
+    }
+
+    #[test]
+    fn duplicate_precedence_keys_are_reported_at_their_line() {
+        let src = r#"
+key_precedence = ["amenity", "shop", "amenity"]
+
+[[rule]]
+category = "food"
+keys = ["amenity"]
+values = ["cafe"]
+"#;
+        let error = parse_error(src);
+        assert!(error.starts_with("my.toml:2: "), "{}", error);
+        assert!(error.contains("\"amenity\" more than once"), "{}", error);
+    }
+}
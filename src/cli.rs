@@ -0,0 +1,376 @@
+// command line interface, parsed with clap
+use crate::geometry::GeometryFormat;
+use crate::node_store::NodeStoreKind;
+use clap::{Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "osm-extractor",
+    version,
+    about = "Extracts POIs and addresses from OSM PBF files into a SQLite database"
+)]
+pub struct Cli {
+    /// Print extra detail (repeat for more)
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// Only print errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Extract POIs and addresses from a PBF file
+    Extract(ExtractArgs),
+    /// Print a summary of a generated database
+    Inspect(InspectArgs),
+    /// Look up POIs and addresses by name in a generated database
+    Query(QueryArgs),
+    /// List POIs in a generated database that are open at a given time
+    OpenAt(OpenAtArgs),
+    /// Print the address, POI and locality nearest to a point in a generated database as JSON
+    Reverse(ReverseArgs),
+    /// Search POIs and addresses in a generated database the way autocomplete would
+    Search(SearchArgs),
+    /// Answer search, reverse and POI lookups over HTTP from a generated database
+    Serve(ServeArgs),
+    /// Read through a PBF file and report what it contains without writing anything
+    Validate(ValidateArgs),
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ExtractArgs {
+    /// Input OSM PBF file
+    pub pbf_file: PathBuf,
+
+    /// Output file path (defaults to osm_data.db, or osm_data.json, .geojson or .geojsonl
+    /// for the other formats)
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Sqlite)]
+    pub format: OutputFormat,
+
+    /// Category mapping file (TOML), replaces the built-in mapping
+    #[arg(short, long, value_name = "FILE")]
+    pub categories: Option<PathBuf>,
+
+    /// Tag keys in order of precedence when an element matches several categories,
+    /// e.g. amenity,shop,tourism (overrides key_precedence from the mapping file)
+    #[arg(long, value_name = "KEYS", value_delimiter = ',')]
+    pub precedence: Option<Vec<String>>,
+
+    /// Skip filling in missing POI addresses from the nearest address point
+    #[arg(long)]
+    pub no_enrich: bool,
+
+    /// Only fill in POI addresses from roads and addresses this close, in metres
+    #[arg(long, value_name = "METRES", default_value_t = 250.0)]
+    pub max_enrich_distance: f64,
+
+    /// Keep the raw OSM tags of POIs and addresses in a JSON tags column
+    #[arg(long)]
+    pub keep_tags: bool,
+
+    /// Only keep these tag keys, e.g. opening_hours,phone,contact:* (defaults to all)
+    #[arg(
+        long,
+        value_name = "KEYS",
+        value_delimiter = ',',
+        requires = "keep_tags"
+    )]
+    pub tag_include: Vec<String>,
+
+    /// Drop these tag keys, e.g. source,note,fixme,name:*
+    #[arg(
+        long,
+        value_name = "KEYS",
+        value_delimiter = ',',
+        requires = "keep_tags"
+    )]
+    pub tag_exclude: Vec<String>,
+
+    /// Worker threads for decoding the PBF file (0 uses every core)
+    #[arg(short = 'j', long, default_value_t = 0)]
+    pub threads: usize,
+
+    /// How node coordinates are held between the two passes
+    #[arg(long, value_enum, default_value_t = NodeStoreKind::Sparse)]
+    pub node_store: NodeStoreKind,
+
+    /// Scan ways and relations first and only store coordinates for the nodes they use
+    /// (one or two extra reads of the file, much less memory)
+    #[arg(long)]
+    pub only_referenced_nodes: bool,
+
+    /// File backing the mmap node store (defaults to a file in the temp directory); it
+    /// must not exist yet, and is deleted when the extraction finishes
+    #[arg(long, value_name = "PATH")]
+    pub node_store_file: Option<PathBuf>,
+
+    /// Only keep POIs and addresses inside min_lon,min_lat,max_lon,max_lat
+    #[arg(
+        long,
+        value_name = "MIN_LON,MIN_LAT,MAX_LON,MAX_LAT",
+        allow_hyphen_values = true
+    )]
+    pub bbox: Option<BoundingBox>,
+
+    /// Also store the outline of closed way and multipolygon POIs in the geometry column
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub geometry: Option<GeometryFormat>,
+}
+
+impl ExtractArgs {
+    pub fn output_path(&self) -> PathBuf {
+        self.output.clone().unwrap_or_else(|| match self.format {
+            OutputFormat::Sqlite => PathBuf::from("osm_data.db"),
+            OutputFormat::Json => PathBuf::from("osm_data.json"),
+            OutputFormat::Geojson => PathBuf::from("osm_data.geojson"),
+            OutputFormat::Geojsonseq => PathBuf::from("osm_data.geojsonl"),
+        })
+    }
+}
+
+#[derive(clap::Args, Debug)]
+pub struct InspectArgs {
+    /// Database produced by the extract command
+    #[arg(default_value = "osm_data.db")]
+    pub db: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct QueryArgs {
+    /// Text to look for at the start of names and addresses
+    pub text: String,
+
+    /// Database produced by the extract command
+    #[arg(long, default_value = "osm_data.db")]
+    pub db: PathBuf,
+
+    /// Maximum number of rows to print per table
+    #[arg(short, long, default_value_t = 10)]
+    pub limit: usize,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct OpenAtArgs {
+    /// Local time to check, "now" or YYYY-MM-DD HH:MM
+    #[arg(default_value = "now")]
+    pub time: String,
+
+    /// Only POIs whose name starts with this text
+    #[arg(long)]
+    pub name: Option<String>,
+
+    /// Only POIs in this category
+    #[arg(long)]
+    pub category: Option<String>,
+
+    /// Database produced by the extract command
+    #[arg(long, default_value = "osm_data.db")]
+    pub db: PathBuf,
+
+    /// Maximum number of rows to print
+    #[arg(short, long, default_value_t = 10)]
+    pub limit: usize,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ReverseArgs {
+    /// Latitude of the point
+    #[arg(allow_negative_numbers = true)]
+    pub lat: f64,
+
+    /// Longitude of the point
+    #[arg(allow_negative_numbers = true)]
+    pub lon: f64,
+
+    /// Database produced by the extract command
+    #[arg(long, default_value = "osm_data.db")]
+    pub db: PathBuf,
+
+    /// Only return addresses and POIs this close, in metres
+    #[arg(long, value_name = "METRES", default_value_t = 1000.0)]
+    pub max_distance: f64,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct SearchArgs {
+    /// Words to look for, each matching the start of a word in names and addresses
+    pub text: String,
+
+    /// Rank results closer to LAT,LON higher
+    #[arg(long, value_name = "LAT,LON", allow_hyphen_values = true)]
+    pub near: Option<LatLon>,
+
+    /// Only POIs in this category
+    #[arg(long)]
+    pub category: Option<String>,
+
+    /// Database produced by the extract command
+    #[arg(long, default_value = "osm_data.db")]
+    pub db: PathBuf,
+
+    /// Maximum number of results
+    #[arg(short, long, default_value_t = 10)]
+    pub limit: usize,
+
+    /// How results are printed
+    #[arg(short, long, value_enum, default_value_t = ResultFormat::Table)]
+    pub format: ResultFormat,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ServeArgs {
+    /// Database produced by the extract command
+    #[arg(long, default_value = "osm_data.db")]
+    pub db: PathBuf,
+
+    /// Address to listen on, use 0.0.0.0 to accept connections from other machines
+    #[arg(long, default_value = "127.0.0.1")]
+    pub host: String,
+
+    /// Port to listen on
+    #[arg(short, long, default_value_t = 8080)]
+    pub port: u16,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ValidateArgs {
+    /// Input OSM PBF file
+    pub pbf_file: PathBuf,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Indexed SQLite database
+    Sqlite,
+    /// Single JSON document with pois and addresses arrays, for debugging
+    Json,
+    /// GeoJSON FeatureCollection of POI and address points
+    Geojson,
+    /// Newline-delimited GeoJSON features, one POI or address per line
+    Geojsonseq,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResultFormat {
+    /// One line per result
+    Table,
+    /// JSON array
+    Json,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct LatLon {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+impl FromStr for LatLon {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (lat, lon) = s
+            .split_once(',')
+            .ok_or_else(|| "expected LAT,LON".to_string())?;
+        let parse = |v: &str| {
+            v.trim()
+                .parse::<f64>()
+                .map_err(|e| format!("invalid number in point: {}", e))
+        };
+        let point = LatLon {
+            lat: parse(lat)?,
+            lon: parse(lon)?,
+        };
+        if !(-90.0..=90.0).contains(&point.lat) || !(-180.0..=180.0).contains(&point.lon) {
+            return Err("latitude must be within ±90 and longitude within ±180".to_string());
+        }
+        Ok(point)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct BoundingBox {
+    pub min_lon: f64,
+    pub min_lat: f64,
+    pub max_lon: f64,
+    pub max_lat: f64,
+}
+
+impl BoundingBox {
+    pub fn contains(&self, lat: f64, lon: f64) -> bool {
+        lat >= self.min_lat && lat <= self.max_lat && lon >= self.min_lon && lon <= self.max_lon
+    }
+}
+
+impl FromStr for BoundingBox {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<f64> = s
+            .split(',')
+            .map(|p| p.trim().parse::<f64>())
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("invalid number in bbox: {}", e))?;
+
+        if parts.len() != 4 {
+            return Err(format!(
+                "expected 4 comma separated values, got {}",
+                parts.len()
+            ));
+        }
+
+        let bbox = BoundingBox {
+            min_lon: parts[0],
+            min_lat: parts[1],
+            max_lon: parts[2],
+            max_lat: parts[3],
+        };
+        if bbox.min_lon > bbox.max_lon || bbox.min_lat > bbox.max_lat {
+            return Err("bbox minimums must not be larger than maximums".to_string());
+        }
+        Ok(bbox)
+    }
+}
+
+// 0 = quiet, 1 = normal progress output, 2+ = verbose
+static VERBOSITY: AtomicU8 = AtomicU8::new(1);
+
+pub fn set_verbosity(cli: &Cli) {
+    let level = if cli.quiet { 0 } else { 1 + cli.verbose };
+    VERBOSITY.store(level, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> u8 {
+    VERBOSITY.load(Ordering::Relaxed)
+}
+
+// progress output, hidden with --quiet
+#[macro_export]
+macro_rules! progress {
+    ($($arg:tt)*) => {
+        if $crate::cli::verbosity() >= 1 {
+            println!($($arg)*);
+        }
+    };
+}
+
+// extra detail, only shown with --verbose
+#[macro_export]
+macro_rules! detail {
+    ($($arg:tt)*) => {
+        if $crate::cli::verbosity() >= 2 {
+            println!($($arg)*);
+        }
+    };
+}
@@ -0,0 +1,120 @@
+// typed poi attributes pulled out of the raw tags: opening hours, contact details and brand
+use crate::opening_hours::OpeningHours;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct PoiDetails {
+    // the tag as mapped, plus its canonical form when we could parse it
+    pub opening_hours: Option<String>,
+    pub opening_hours_normalized: Option<String>,
+    // E.164, e.g. +17055551234
+    pub phone: Option<String>,
+    pub website: Option<String>,
+    pub brand: Option<String>,
+    pub brand_wikidata: Option<String>,
+    pub operator: Option<String>,
+}
+
+impl PoiDetails {
+    pub fn from_tags(tags: &HashMap<String, String>) -> PoiDetails {
+        let tag = |key: &str| {
+            tags.get(key)
+                .map(|v| v.trim())
+                .filter(|v| !v.is_empty())
+                .map(str::to_string)
+        };
+        let opening_hours = tag("opening_hours");
+
+        PoiDetails {
+            opening_hours_normalized: opening_hours
+                .as_deref()
+                .and_then(|v| OpeningHours::parse(v).ok())
+                .map(|h| h.to_string()),
+            opening_hours,
+            phone: tag("phone")
+                .or_else(|| tag("contact:phone"))
+                .and_then(|v| normalize_phone(&v)),
+            website: tag("website")
+                .or_else(|| tag("contact:website"))
+                .and_then(|v| normalize_website(&v)),
+            brand: tag("brand"),
+            brand_wikidata: tag("brand:wikidata").filter(|v| is_wikidata_id(v)),
+            operator: tag("operator"),
+        }
+    }
+}
+
+// the first of several ;-separated numbers in E.164, numbers without a country code are
+// taken to be North American (+1) since that's the numbering plan the admin levels assume
+fn normalize_phone(value: &str) -> Option<String> {
+    let number = value.split(';').next()?.trim();
+    // extensions can't be written in E.164
+    let number = ["ext", "x", "#"]
+        .iter()
+        .filter_map(|sep| number.to_ascii_lowercase().find(sep))
+        .min()
+        .map_or(number, |pos| &number[..pos]);
+
+    let international = number.starts_with('+') || number.starts_with("00");
+    if number
+        .chars()
+        .any(|c| !(c.is_ascii_digit() || " +-.()/".contains(c)))
+    {
+        return None;
+    }
+    let digits: String = number.chars().filter(char::is_ascii_digit).collect();
+
+    let e164 = if number.starts_with('+') {
+        digits
+    } else if international {
+        digits[2..].to_string()
+    } else {
+        match digits.len() {
+            10 => format!("1{}", digits),
+            11 if digits.starts_with('1') => digits,
+            _ => return None,
+        }
+    };
+    // country codes never start with 0 and E.164 allows at most 15 digits
+    (e164.len() >= 8 && e164.len() <= 15 && !e164.starts_with('0')).then(|| format!("+{}", e164))
+}
+
+// an absolute http(s) url with a lowercase scheme and host, https:// is assumed when the
+// scheme is missing
+fn normalize_website(value: &str) -> Option<String> {
+    let url = value.split(';').next()?.trim();
+    if url.is_empty() || url.contains(char::is_whitespace) {
+        return None;
+    }
+
+    let (scheme, rest) = match url.split_once("://") {
+        Some((scheme, rest)) => (scheme.to_ascii_lowercase(), rest),
+        None => ("https".to_string(), url),
+    };
+    if scheme != "http" && scheme != "https" {
+        return None;
+    }
+
+    let host_end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let (host, path) = rest.split_at(host_end);
+    let host = host.to_ascii_lowercase();
+    let valid_host = host.contains('.')
+        && host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-.:".contains(c))
+        && !host.starts_with('.')
+        && !host.ends_with('.');
+    if !valid_host {
+        return None;
+    }
+
+    let path = if path == "/" { "" } else { path };
+    Some(format!("{}://{}{}", scheme, host, path))
+}
+
+fn is_wikidata_id(value: &str) -> bool {
+    value
+        .strip_prefix('Q')
+        .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+}
@@ -0,0 +1,474 @@
+// lookups against a generated database, shared by the query subcommands and the server
+use crate::geometry::{self, Coord};
+use crate::places::{self, Locality, Place};
+use rusqlite::{params, Connection, OpenFlags, Result as SqlResult, Row};
+use serde::Serialize;
+use std::path::Path;
+
+// reverse lookups start this close and widen until something turns up
+const INITIAL_RADIUS_M: f64 = 100.0;
+
+// full-text matches fetched per table before ranking, so a close or important match that
+// ranks low on text alone still gets a chance
+const SEARCH_CANDIDATES: usize = 200;
+
+// with --near, a match this far away scores half of an equally good one right here
+const SEARCH_HALF_SCORE_M: f64 = 5_000.0;
+
+#[derive(Debug, Serialize)]
+pub struct AddressHit {
+    pub osm_type: String,
+    pub id: i64,
+    pub housenumber: String,
+    pub street: String,
+    pub city: String,
+    pub postcode: String,
+    pub full_address: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub municipality: String,
+    pub county: String,
+    pub province: String,
+    pub distance_m: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PoiHit {
+    pub osm_type: String,
+    pub id: i64,
+    pub name: String,
+    pub category: String,
+    pub subcategory: String,
+    pub full_address: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub municipality: String,
+    pub county: String,
+    pub province: String,
+    pub distance_m: f64,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct LocalityNames {
+    pub city: String,
+    pub suburb: String,
+    pub municipality: String,
+    pub county: String,
+    pub province: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReverseResult {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub address: Option<AddressHit>,
+    pub poi: Option<PoiHit>,
+    // the smallest area poi around the point, like the park or campus it's in
+    pub area: Option<PoiHit>,
+    pub locality: LocalityNames,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchHit {
+    // "poi" or "address"
+    pub kind: &'static str,
+    pub osm_type: String,
+    pub id: i64,
+    // the full address for addresses
+    pub name: String,
+    pub category: Option<String>,
+    pub full_address: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub distance_m: Option<f64>,
+    pub score: f64,
+}
+
+pub struct SearchQuery<'a> {
+    pub text: &'a str,
+    pub near: Option<Coord>,
+    // only pois in this category, addresses are left out
+    pub category: Option<&'a str>,
+    pub limit: usize,
+}
+
+pub struct Geocoder {
+    conn: Connection,
+}
+
+const ADDRESS_COLUMNS: &str = "a.osm_type, a.id, a.housenumber, a.street, a.city, a.postcode,
+    a.full_address, a.latitude, a.longitude, a.municipality, a.county, a.province";
+
+const POI_COLUMNS: &str = "p.osm_type, p.id, p.name, p.category, p.subcategory, p.full_address,
+    p.latitude, p.longitude, p.municipality, p.county, p.province";
+
+impl Geocoder {
+    pub fn open(db: &Path) -> Result<Geocoder, Box<dyn std::error::Error>> {
+        let conn = Connection::open_with_flags(db, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|e| format!("Could not open {}: {}", db.display(), e))?;
+
+        // databases from before the search and spatial indexes can't answer these lookups
+        let has_index: bool = conn.query_row(
+            "SELECT COUNT(*) = 4 FROM sqlite_master
+            WHERE name IN ('pois_fts', 'addresses_fts', 'pois_rtree', 'addresses_rtree')",
+            [],
+            |r| r.get(0),
+        )?;
+        if !has_index {
+            return Err(format!(
+                "{} has no search or spatial index, extract it again with this version",
+                db.display()
+            )
+            .into());
+        }
+        Ok(Geocoder { conn })
+    }
+
+    // the nearest address and poi within `max_distance_m`, the area the point is in and
+    // the locality
+    pub fn reverse(&self, lat: f64, lon: f64, max_distance_m: f64) -> SqlResult<ReverseResult> {
+        let address = self.nearest_address(lat, lon, max_distance_m)?;
+        let poi = self.nearest_poi(lat, lon, max_distance_m)?;
+        let area = self.containing_poi(lat, lon)?;
+
+        // admin areas come from the nearest address or poi, both were placed in them
+        let mut locality = LocalityNames::default();
+        if let Some(a) = &address {
+            locality.city = a.city.clone();
+            locality.municipality = a.municipality.clone();
+            locality.county = a.county.clone();
+            locality.province = a.province.clone();
+        } else if let Some(p) = &poi {
+            locality.municipality = p.municipality.clone();
+            locality.county = p.county.clone();
+            locality.province = p.province.clone();
+        }
+        // place nodes know the city and suburb of the point itself
+        let nearby_places = self.places_near(lat, lon)?;
+        if let Some(city) = places::best_fit(&nearby_places, lat, lon, Locality::City) {
+            locality.city = city.name.clone();
+        }
+        if let Some(suburb) = places::best_fit(&nearby_places, lat, lon, Locality::Suburb) {
+            locality.suburb = suburb.name.clone();
+        }
+
+        Ok(ReverseResult {
+            latitude: lat,
+            longitude: lon,
+            address,
+            poi,
+            area,
+            locality,
+        })
+    }
+
+    fn nearest_address(
+        &self,
+        lat: f64,
+        lon: f64,
+        max_distance_m: f64,
+    ) -> SqlResult<Option<AddressHit>> {
+        let sql = format!(
+            "SELECT {} FROM addresses_rtree r JOIN addresses a ON a.address_id = r.id
+            WHERE r.max_lon >= ?1 AND r.min_lon <= ?2 AND r.max_lat >= ?3 AND r.min_lat <= ?4",
+            ADDRESS_COLUMNS
+        );
+        self.nearest(&sql, (lat, lon), max_distance_m, |r| {
+            address_hit(r).map(|a| (a.latitude, a.longitude, a))
+        })
+        .map(|hit| hit.map(|(distance_m, a)| AddressHit { distance_m, ..a }))
+    }
+
+    fn nearest_poi(&self, lat: f64, lon: f64, max_distance_m: f64) -> SqlResult<Option<PoiHit>> {
+        let sql = format!(
+            "SELECT {} FROM pois_rtree r JOIN pois p ON p.poi_id = r.id
+            WHERE r.max_lon >= ?1 AND r.min_lon <= ?2 AND r.max_lat >= ?3 AND r.min_lat <= ?4",
+            POI_COLUMNS
+        );
+        self.nearest(&sql, (lat, lon), max_distance_m, |r| {
+            poi_hit(r).map(|p| (p.latitude, p.longitude, p))
+        })
+        .map(|hit| hit.map(|(distance_m, p)| PoiHit { distance_m, ..p }))
+    }
+
+    // the smallest way or relation poi whose extent holds the point, extents are boxes
+    // so this can be off at the edges of odd-shaped areas
+    fn containing_poi(&self, lat: f64, lon: f64) -> SqlResult<Option<PoiHit>> {
+        let sql = format!(
+            "SELECT {} FROM pois_rtree r JOIN pois p ON p.poi_id = r.id
+            WHERE r.min_lon <= ?1 AND r.max_lon >= ?1 AND r.min_lat <= ?2 AND r.max_lat >= ?2
+                AND p.osm_type != 'node'
+            ORDER BY (r.max_lon - r.min_lon) * (r.max_lat - r.min_lat), p.poi_id
+            LIMIT 1",
+            POI_COLUMNS
+        );
+        let mut stmt = self.conn.prepare_cached(&sql)?;
+        let mut rows = stmt.query_map(params![lon, lat], poi_hit)?;
+        rows.next().transpose().map(|hit| {
+            hit.map(|p| PoiHit {
+                distance_m: geometry::haversine_m((lat, lon), (p.latitude, p.longitude)),
+                ..p
+            })
+        })
+    }
+
+    // pois and addresses matching every word of the text as a prefix, best first
+    // score = text × importance / (1 + distance / SEARCH_HALF_SCORE_M), where
+    // - text is the bm25 score divided by the best bm25 among the candidates from the same
+    //   fts table, since bm25 from pois_fts and addresses_fts aren't on the same scale;
+    //   the top poi and the top address both get 1
+    // - importance is 1 + 0.25·ln(1 + name variants) + 0.25 with a brand:wikidata id for
+    //   pois, 1 for addresses
+    // - the distance term only applies with `near`, halving the score at SEARCH_HALF_SCORE_M
+    pub fn search(
+        &self,
+        query: &SearchQuery,
+    ) -> Result<Vec<SearchHit>, Box<dyn std::error::Error>> {
+        let Some(fts_query) = fts_prefix_query(query.text) else {
+            return Err(format!("Nothing to search for in \"{}\"", query.text).into());
+        };
+
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT p.osm_type, p.id, p.name, p.category, p.full_address, p.latitude, p.longitude,
+                -pois_fts.rank,
+                (SELECT COUNT(*) FROM poi_names n WHERE n.osm_type = p.osm_type AND n.id = p.id),
+                p.brand_wikidata IS NOT NULL
+            FROM pois_fts JOIN pois p ON p.poi_id = pois_fts.rowid
+            WHERE pois_fts MATCH ?1 AND (?2 IS NULL OR p.category = ?2)
+            ORDER BY pois_fts.rank LIMIT ?3",
+        )?;
+        let rows = stmt.query_map(
+            params![fts_query, query.category, SEARCH_CANDIDATES as i64],
+            |r| {
+                let names: i64 = r.get(8)?;
+                let has_wikidata: bool = r.get(9)?;
+                let importance =
+                    1.0 + 0.25 * (names as f64).ln_1p() + 0.25 * has_wikidata as u8 as f64;
+                let hit = SearchHit {
+                    kind: "poi",
+                    osm_type: r.get(0)?,
+                    id: r.get(1)?,
+                    name: r.get(2)?,
+                    category: r.get(3)?,
+                    full_address: text(r, 4)?,
+                    latitude: r.get(5)?,
+                    longitude: r.get(6)?,
+                    distance_m: None,
+                    score: r.get(7)?,
+                };
+                Ok((hit, importance))
+            },
+        )?;
+        let (mut hits, importance): (Vec<SearchHit>, Vec<f64>) =
+            rows.collect::<SqlResult<Vec<_>>>()?.into_iter().unzip();
+        normalize_text_scores(&mut hits);
+        for (hit, importance) in hits.iter_mut().zip(importance) {
+            hit.score *= importance;
+        }
+
+        if query.category.is_none() {
+            let mut stmt = self.conn.prepare_cached(
+                "SELECT a.osm_type, a.id, a.full_address, a.latitude, a.longitude, -addresses_fts.rank
+                FROM addresses_fts JOIN addresses a ON a.address_id = addresses_fts.rowid
+                WHERE addresses_fts MATCH ?1
+                ORDER BY addresses_fts.rank LIMIT ?2",
+            )?;
+            let rows = stmt.query_map(params![fts_query, SEARCH_CANDIDATES as i64], |r| {
+                Ok(SearchHit {
+                    kind: "address",
+                    osm_type: r.get(0)?,
+                    id: r.get(1)?,
+                    name: text(r, 2)?,
+                    category: None,
+                    full_address: text(r, 2)?,
+                    latitude: r.get(3)?,
+                    longitude: r.get(4)?,
+                    distance_m: None,
+                    score: r.get(5)?,
+                })
+            })?;
+            let mut addresses = rows.collect::<SqlResult<Vec<_>>>()?;
+            normalize_text_scores(&mut addresses);
+            hits.extend(addresses);
+        }
+
+        if let Some(near) = query.near {
+            for hit in &mut hits {
+                let distance = geometry::haversine_m(near, (hit.latitude, hit.longitude));
+                hit.score /= 1.0 + distance / SEARCH_HALF_SCORE_M;
+                hit.distance_m = Some(distance);
+            }
+        }
+        hits.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| (a.kind, &a.osm_type, a.id).cmp(&(b.kind, &b.osm_type, b.id)))
+        });
+        hits.truncate(query.limit);
+        Ok(hits)
+    }
+
+    // pois within `radius_m` of the point, nearest first
+    pub fn nearby_pois(
+        &self,
+        lat: f64,
+        lon: f64,
+        radius_m: f64,
+        category: Option<&str>,
+        limit: usize,
+    ) -> SqlResult<Vec<PoiHit>> {
+        let sql = format!(
+            "SELECT {} FROM pois_rtree r JOIN pois p ON p.poi_id = r.id
+            WHERE r.max_lon >= ?1 AND r.min_lon <= ?2 AND r.max_lat >= ?3 AND r.min_lat <= ?4
+                AND (?5 IS NULL OR p.category = ?5)",
+            POI_COLUMNS
+        );
+        let ((min_lat, min_lon), (max_lat, max_lon)) = geometry::box_around((lat, lon), radius_m);
+        let mut stmt = self.conn.prepare_cached(&sql)?;
+        let mut hits = Vec::new();
+        for row in stmt.query_map(
+            params![min_lon, max_lon, min_lat, max_lat, category],
+            poi_hit,
+        )? {
+            let poi = row?;
+            let distance_m = geometry::haversine_m((lat, lon), (poi.latitude, poi.longitude));
+            if distance_m <= radius_m {
+                hits.push(PoiHit { distance_m, ..poi });
+            }
+        }
+
+        hits.sort_by(|a, b| {
+            a.distance_m
+                .total_cmp(&b.distance_m)
+                .then_with(|| (&a.osm_type, a.id).cmp(&(&b.osm_type, b.id)))
+        });
+        hits.truncate(limit);
+        Ok(hits)
+    }
+
+    pub fn poi(&self, osm_type: &str, id: i64) -> SqlResult<Option<PoiHit>> {
+        let sql = format!(
+            "SELECT {} FROM pois p WHERE p.osm_type = ?1 AND p.id = ?2",
+            POI_COLUMNS
+        );
+        let mut stmt = self.conn.prepare_cached(&sql)?;
+        let mut rows = stmt.query_map(params![osm_type, id], poi_hit)?;
+        rows.next().transpose()
+    }
+
+    // place nodes close enough to fill a locality at the point
+    fn places_near(&self, lat: f64, lon: f64) -> SqlResult<Vec<Place>> {
+        let ((min_lat, min_lon), (max_lat, max_lon)) =
+            geometry::box_around((lat, lon), places::MAX_REACH_M);
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT id, name, place, population, latitude, longitude FROM places
+            WHERE longitude BETWEEN ?1 AND ?2 AND latitude BETWEEN ?3 AND ?4",
+        )?;
+        let rows = stmt.query_map(params![min_lon, max_lon, min_lat, max_lat], |r| {
+            Ok(Place {
+                id: r.get(0)?,
+                name: r.get(1)?,
+                place: r.get(2)?,
+                population: r.get(3)?,
+                latitude: r.get(4)?,
+                longitude: r.get(5)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    // closest row of an r*tree `sql` query taking min_lon, max_lon, min_lat, max_lat, with
+    // its distance; the box grows until a row turns up inside the circle it covers
+    fn nearest<T>(
+        &self,
+        sql: &str,
+        center: Coord,
+        max_distance_m: f64,
+        read: impl Fn(&Row) -> SqlResult<(f64, f64, T)>,
+    ) -> SqlResult<Option<(f64, T)>> {
+        let mut stmt = self.conn.prepare_cached(sql)?;
+        // each radius is four times the last, up to max_distance_m; the comparison is
+        // false for NaN, so a bad bound ends the search instead of widening forever
+        let radii = std::iter::successors(Some(INITIAL_RADIUS_M.min(max_distance_m)), |r| {
+            (*r < max_distance_m).then(|| (r * 4.0).min(max_distance_m))
+        });
+
+        for radius in radii {
+            let ((min_lat, min_lon), (max_lat, max_lon)) = geometry::box_around(center, radius);
+            let mut best: Option<(f64, T)> = None;
+            for row in stmt.query_map(params![min_lon, max_lon, min_lat, max_lat], |r| read(r))? {
+                let (lat, lon, value) = row?;
+                let distance = geometry::haversine_m(center, (lat, lon));
+                if distance <= radius && best.as_ref().is_none_or(|(d, _)| distance < *d) {
+                    best = Some((distance, value));
+                }
+            }
+
+            if best.is_some() {
+                return Ok(best);
+            }
+        }
+        Ok(None)
+    }
+}
+
+// divides the bm25 scores of one fts table's matches by the best of them, see search
+fn normalize_text_scores(hits: &mut [SearchHit]) {
+    let best = hits.iter().map(|h| h.score).fold(0.0, f64::max);
+    if best > 0.0 {
+        for hit in hits {
+            hit.score /= best;
+        }
+    }
+}
+
+// "Tim Hort, Barrie" as the fts5 query "tim"* "hort"* "barrie"*, every word a prefix that
+// has to match; None when the text has no words at all
+fn fts_prefix_query(text: &str) -> Option<String> {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| format!("\"{}\"*", w.to_lowercase()))
+        .collect();
+    (!words.is_empty()).then(|| words.join(" "))
+}
+
+fn address_hit(r: &Row) -> SqlResult<AddressHit> {
+    Ok(AddressHit {
+        osm_type: r.get(0)?,
+        id: r.get(1)?,
+        housenumber: text(r, 2)?,
+        street: text(r, 3)?,
+        city: text(r, 4)?,
+        postcode: text(r, 5)?,
+        full_address: text(r, 6)?,
+        latitude: r.get(7)?,
+        longitude: r.get(8)?,
+        municipality: text(r, 9)?,
+        county: text(r, 10)?,
+        province: text(r, 11)?,
+        distance_m: 0.0,
+    })
+}
+
+fn poi_hit(r: &Row) -> SqlResult<PoiHit> {
+    Ok(PoiHit {
+        osm_type: r.get(0)?,
+        id: r.get(1)?,
+        name: r.get(2)?,
+        category: r.get(3)?,
+        subcategory: text(r, 4)?,
+        full_address: text(r, 5)?,
+        latitude: r.get(6)?,
+        longitude: r.get(7)?,
+        municipality: text(r, 8)?,
+        county: text(r, 9)?,
+        province: text(r, 10)?,
+        distance_m: 0.0,
+    })
+}
+
+// nullable text columns come back as empty strings, like the extracted structs have them
+fn text(r: &Row, idx: usize) -> SqlResult<String> {
+    Ok(r.get::<_, Option<String>>(idx)?.unwrap_or_default())
+}
@@ -0,0 +1,593 @@
+// geometry helpers for turning way and relation members into areas
+// coordinates are (lat, lon) tuples, the same layout as the pass 1 node store
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+pub type Coord = (f64, f64);
+
+// joins open way segments end to end into closed rings
+// ways that never close up are dropped since they don't describe an area
+pub fn assemble_rings(mut segments: Vec<Vec<Coord>>) -> Vec<Vec<Coord>> {
+    let mut rings = Vec::new();
+    segments.retain(|s| s.len() >= 2);
+
+    while let Some(mut ring) = segments.pop() {
+        loop {
+            if ring.len() >= 4 && ring.first() == ring.last() {
+                rings.push(ring);
+                break;
+            }
+
+            let tail = *ring.last().unwrap();
+            let next = segments
+                .iter()
+                .position(|s| s.first() == Some(&tail) || s.last() == Some(&tail));
+
+            match next {
+                Some(pos) => {
+                    let mut segment = segments.swap_remove(pos);
+                    if segment.first() != Some(&tail) {
+                        segment.reverse();
+                    }
+                    ring.extend(segment.into_iter().skip(1));
+                }
+                None => break,
+            }
+        }
+    }
+
+    rings
+}
+
+// shoelace formula, returns signed area (in square degrees) and the area-weighted centroid
+pub fn ring_area_centroid(ring: &[Coord]) -> Option<(f64, Coord)> {
+    if ring.len() < 4 {
+        return None;
+    }
+
+    // shifting to the first vertex keeps the cross products small and precise
+    let (lat0, lon0) = ring[0];
+    let mut area = 0.0;
+    let mut cx = 0.0;
+    let mut cy = 0.0;
+
+    for pair in ring.windows(2) {
+        let (y1, x1) = (pair[0].0 - lat0, pair[0].1 - lon0);
+        let (y2, x2) = (pair[1].0 - lat0, pair[1].1 - lon0);
+        let cross = x1 * y2 - x2 * y1;
+        area += cross;
+        cx += (x1 + x2) * cross;
+        cy += (y1 + y2) * cross;
+    }
+
+    if area.abs() < f64::EPSILON {
+        return None;
+    }
+
+    area /= 2.0;
+    Some((area, (lat0 + cy / (6.0 * area), lon0 + cx / (6.0 * area))))
+}
+
+// area-weighted centroid over several outer rings, used for multipolygons
+pub fn rings_centroid(rings: &[Vec<Coord>]) -> Option<Coord> {
+    let mut total_area = 0.0;
+    let mut lat_sum = 0.0;
+    let mut lon_sum = 0.0;
+
+    for ring in rings {
+        if let Some((area, (lat, lon))) = ring_area_centroid(ring) {
+            let weight = area.abs();
+            total_area += weight;
+            lat_sum += lat * weight;
+            lon_sum += lon * weight;
+        }
+    }
+
+    if total_area > 0.0 {
+        Some((lat_sum / total_area, lon_sum / total_area))
+    } else {
+        None
+    }
+}
+
+// plain average of the vertices, the fallback when we can't build a proper ring
+pub fn mean_point(coords: &[Coord]) -> Option<Coord> {
+    if coords.is_empty() {
+        return None;
+    }
+
+    let (lat_sum, lon_sum) = coords
+        .iter()
+        .fold((0.0, 0.0), |(la, lo), (lat, lon)| (la + lat, lo + lon));
+    let n = coords.len() as f64;
+    Some((lat_sum / n, lon_sum / n))
+}
+
+// an outer ring with the inner rings (holes) that sit inside it
+#[derive(Debug, Clone)]
+pub struct Polygon {
+    pub outer: Vec<Coord>,
+    pub holes: Vec<Vec<Coord>>,
+}
+
+// a closed way with at least three distinct corners is an area
+pub fn closed_way_polygon(coords: &[Coord]) -> Option<Polygon> {
+    if coords.len() >= 4 && coords.first() == coords.last() {
+        Some(Polygon {
+            outer: coords.to_vec(),
+            holes: Vec::new(),
+        })
+    } else {
+        None
+    }
+}
+
+// hands every inner ring to the first outer ring that contains it
+pub fn build_polygons(outer_rings: Vec<Vec<Coord>>, inner_rings: Vec<Vec<Coord>>) -> Vec<Polygon> {
+    let mut polygons: Vec<Polygon> = outer_rings
+        .into_iter()
+        .map(|outer| Polygon {
+            outer,
+            holes: Vec::new(),
+        })
+        .collect();
+
+    for inner in inner_rings {
+        let owner = polygons
+            .iter_mut()
+            .find(|p| point_in_ring(inner[0], &p.outer));
+        if let Some(polygon) = owner {
+            polygon.holes.push(inner);
+        }
+    }
+
+    polygons
+}
+
+// even-odd ray casting
+pub fn point_in_ring((lat, lon): Coord, ring: &[Coord]) -> bool {
+    let mut inside = false;
+    for pair in ring.windows(2) {
+        let (lat1, lon1) = pair[0];
+        let (lat2, lon2) = pair[1];
+        if (lat1 > lat) != (lat2 > lat) && lon < (lon2 - lon1) * (lat - lat1) / (lat2 - lat1) + lon1
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+// representative points of an area, see area_points
+#[derive(Debug, Clone, Copy)]
+pub struct AreaPoints {
+    pub centroid: Coord,
+    pub interior: Coord,
+}
+
+// area-weighted centroid (holes subtracted) and an interior point, the pole of
+// inaccessibility, or the centroid for slivers too thin to place one in; None only when
+// the rings enclose no area at all
+pub fn area_points(polygons: &[Polygon]) -> Option<AreaPoints> {
+    let mut total_area = 0.0;
+    let mut lat_sum = 0.0;
+    let mut lon_sum = 0.0;
+
+    for polygon in polygons {
+        let rings =
+            std::iter::once((&polygon.outer, 1.0)).chain(polygon.holes.iter().map(|h| (h, -1.0)));
+        for (ring, sign) in rings {
+            if let Some((area, (lat, lon))) = ring_area_centroid(ring) {
+                let weight = area.abs() * sign;
+                total_area += weight;
+                lat_sum += lat * weight;
+                lon_sum += lon * weight;
+            }
+        }
+    }
+
+    if total_area <= 0.0 {
+        return None;
+    }
+    let centroid = (lat_sum / total_area, lon_sum / total_area);
+
+    // the interior point comes from whichever part of a multipolygon has the most room,
+    // slivers too thin for the pole search fall back to the centroid
+    let interior = polygons
+        .iter()
+        .filter_map(|p| pole_of_inaccessibility(p, centroid))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map_or(centroid, |(point, _)| point);
+
+    Some(AreaPoints { centroid, interior })
+}
+
+// polylabel: the interior point farthest from any edge, found by refining a grid of cells
+// https://github.com/mapbox/polylabel
+// works in a local equirectangular projection so a degree of longitude isn't worth as
+// much as a degree of latitude, returns the point and its distance to the nearest edge
+const POLE_MAX_CELLS: usize = 50_000;
+
+#[derive(Clone, Copy)]
+struct PoleCell {
+    x: f64,
+    y: f64,
+    half: f64,
+    distance: f64,
+    max_distance: f64,
+}
+
+impl PoleCell {
+    fn new(x: f64, y: f64, half: f64, rings: &[Vec<(f64, f64)>]) -> PoleCell {
+        let distance = signed_distance(x, y, rings);
+        PoleCell {
+            x,
+            y,
+            half,
+            distance,
+            max_distance: distance + half * std::f64::consts::SQRT_2,
+        }
+    }
+}
+
+impl PartialEq for PoleCell {
+    fn eq(&self, other: &Self) -> bool {
+        self.max_distance == other.max_distance
+    }
+}
+
+impl Eq for PoleCell {}
+
+impl PartialOrd for PoleCell {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PoleCell {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.max_distance.total_cmp(&other.max_distance)
+    }
+}
+
+fn pole_of_inaccessibility(polygon: &Polygon, hint: Coord) -> Option<(Coord, f64)> {
+    let scale = hint.0.to_radians().cos();
+    let project = |ring: &Vec<Coord>| -> Vec<(f64, f64)> {
+        ring.iter().map(|&(lat, lon)| (lon * scale, lat)).collect()
+    };
+    let rings: Vec<Vec<(f64, f64)>> = std::iter::once(&polygon.outer)
+        .chain(polygon.holes.iter())
+        .map(project)
+        .collect();
+
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (f64::MAX, f64::MAX, f64::MIN, f64::MIN);
+    for &(x, y) in &rings[0] {
+        min_x = min_x.min(x);
+        min_y = min_y.min(y);
+        max_x = max_x.max(x);
+        max_y = max_y.max(y);
+    }
+    let width = max_x - min_x;
+    let height = max_y - min_y;
+    if width.min(height).is_nan() || width.min(height) <= 0.0 {
+        return None;
+    }
+    // about a thousandth of the polygon's size, a few cm for a building
+    let precision = (width.max(height) / 1000.0).max(1e-9);
+    // no finer than the precision, or a long sliver would start with millions of cells
+    let cell_size = precision.max(width.min(height));
+
+    let mut queue = std::collections::BinaryHeap::new();
+    let half = cell_size / 2.0;
+    let mut x = min_x;
+    while x < max_x {
+        let mut y = min_y;
+        while y < max_y {
+            queue.push(PoleCell::new(x + half, y + half, half, &rings));
+            y += cell_size;
+        }
+        x += cell_size;
+    }
+
+    // the centroid is a good first guess for most shapes, the bbox center for the rest
+    let mut best = PoleCell::new(hint.1 * scale, hint.0, 0.0, &rings);
+    let center = PoleCell::new(min_x + width / 2.0, min_y + height / 2.0, 0.0, &rings);
+    if center.distance > best.distance {
+        best = center;
+    }
+
+    let mut visited = 0;
+    while let Some(cell) = queue.pop() {
+        if cell.distance > best.distance {
+            best = cell;
+        }
+        visited += 1;
+        if cell.max_distance - best.distance <= precision || visited > POLE_MAX_CELLS {
+            continue;
+        }
+
+        let h = cell.half / 2.0;
+        for (dx, dy) in [(-h, -h), (h, -h), (-h, h), (h, h)] {
+            queue.push(PoleCell::new(cell.x + dx, cell.y + dy, h, &rings));
+        }
+    }
+
+    if best.distance <= 0.0 {
+        return None;
+    }
+    Some(((best.y, best.x / scale), best.distance))
+}
+
+// distance from (x, y) to the nearest ring edge, negative when outside the polygon
+fn signed_distance(x: f64, y: f64, rings: &[Vec<(f64, f64)>]) -> f64 {
+    let mut inside = false;
+    let mut min_dist_sq = f64::INFINITY;
+
+    for ring in rings {
+        for pair in ring.windows(2) {
+            let (ax, ay) = pair[0];
+            let (bx, by) = pair[1];
+            if (ay > y) != (by > y) && x < (bx - ax) * (y - ay) / (by - ay) + ax {
+                inside = !inside;
+            }
+            min_dist_sq = min_dist_sq.min(segment_distance_sq(x, y, ax, ay, bx, by));
+        }
+    }
+
+    let dist = min_dist_sq.sqrt();
+    if inside {
+        dist
+    } else {
+        -dist
+    }
+}
+
+// squared distance from point p to the segment a-b, in whatever plane units they use
+pub fn segment_distance_sq(px: f64, py: f64, ax: f64, ay: f64, bx: f64, by: f64) -> f64 {
+    let (cx, cy) = closest_on_segment(px, py, ax, ay, bx, by);
+    (cx - px) * (cx - px) + (cy - py) * (cy - py)
+}
+
+// the point of the segment a-b closest to p
+pub fn closest_on_segment(px: f64, py: f64, ax: f64, ay: f64, bx: f64, by: f64) -> (f64, f64) {
+    let (dx, dy) = (bx - ax, by - ay);
+    let len_sq = dx * dx + dy * dy;
+    let t = if len_sq > 0.0 {
+        (((px - ax) * dx + (py - ay) * dy) / len_sq).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (ax + t * dx, ay + t * dy)
+}
+
+// south-west and north-east corners of everything in `coords`
+pub fn bounds(coords: &[Coord]) -> Option<(Coord, Coord)> {
+    let (&first, rest) = coords.split_first()?;
+    Some(rest.iter().fold((first, first), |(min, max), &(lat, lon)| {
+        (
+            (min.0.min(lat), min.1.min(lon)),
+            (max.0.max(lat), max.1.max(lon)),
+        )
+    }))
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GeometryFormat {
+    /// Well-known binary Polygon/MultiPolygon blobs, readable by SpatiaLite, GDAL and shapely
+    Wkb,
+    /// Google encoded polyline text, one string per ring
+    Polyline,
+}
+
+// an encoded area outline, written to the pois geometry column
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Geometry {
+    Polyline(String),
+    Wkb(Vec<u8>),
+}
+
+pub fn encode_geometry(polygons: &[Polygon], format: GeometryFormat) -> Option<Geometry> {
+    if polygons.is_empty() {
+        return None;
+    }
+    Some(match format {
+        GeometryFormat::Wkb => Geometry::Wkb(to_wkb(polygons)),
+        GeometryFormat::Polyline => Geometry::Polyline(to_polylines(polygons)),
+    })
+}
+
+// little endian wkb, a single polygon is written as Polygon, anything more as MultiPolygon
+const WKB_POLYGON: u32 = 3;
+const WKB_MULTIPOLYGON: u32 = 6;
+
+fn to_wkb(polygons: &[Polygon]) -> Vec<u8> {
+    let mut wkb = Vec::new();
+    if let [polygon] = polygons {
+        write_wkb_polygon(&mut wkb, polygon);
+    } else {
+        wkb.push(1);
+        wkb.extend_from_slice(&WKB_MULTIPOLYGON.to_le_bytes());
+        wkb.extend_from_slice(&(polygons.len() as u32).to_le_bytes());
+        for polygon in polygons {
+            write_wkb_polygon(&mut wkb, polygon);
+        }
+    }
+    wkb
+}
+
+fn write_wkb_polygon(wkb: &mut Vec<u8>, polygon: &Polygon) {
+    wkb.push(1);
+    wkb.extend_from_slice(&WKB_POLYGON.to_le_bytes());
+    wkb.extend_from_slice(&(polygon.holes.len() as u32 + 1).to_le_bytes());
+    for ring in std::iter::once(&polygon.outer).chain(polygon.holes.iter()) {
+        wkb.extend_from_slice(&(ring.len() as u32).to_le_bytes());
+        // wkb is x/y, so longitude comes first
+        for &(lat, lon) in ring {
+            wkb.extend_from_slice(&lon.to_le_bytes());
+            wkb.extend_from_slice(&lat.to_le_bytes());
+        }
+    }
+}
+
+// rings of one polygon are separated by spaces (outer ring first), polygons by ';'
+// neither character can appear inside an encoded polyline
+fn to_polylines(polygons: &[Polygon]) -> String {
+    polygons
+        .iter()
+        .map(|polygon| {
+            std::iter::once(&polygon.outer)
+                .chain(polygon.holes.iter())
+                .map(|ring| encode_polyline(ring))
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+// https://developers.google.com/maps/documentation/utilities/polylinealgorithm, precision 5
+fn encode_polyline(ring: &[Coord]) -> String {
+    let mut encoded = String::new();
+    let (mut prev_lat, mut prev_lon) = (0i64, 0i64);
+    for &(lat, lon) in ring {
+        let lat = (lat * 1e5).round() as i64;
+        let lon = (lon * 1e5).round() as i64;
+        encode_polyline_value(lat - prev_lat, &mut encoded);
+        encode_polyline_value(lon - prev_lon, &mut encoded);
+        prev_lat = lat;
+        prev_lon = lon;
+    }
+    encoded
+}
+
+fn encode_polyline_value(delta: i64, out: &mut String) {
+    let mut value = if delta < 0 { !(delta << 1) } else { delta << 1 };
+    while value >= 0x20 {
+        out.push((((value & 0x1f) | 0x20) as u8 + 63) as char);
+        value >>= 5;
+    }
+    out.push((value as u8 + 63) as char);
+}
+
+const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+// great-circle distance in metres
+pub fn haversine_m((lat1, lon1): Coord, (lat2, lon2): Coord) -> f64 {
+    let dlat = (lat2 - lat1).to_radians();
+    let dlon = (lon2 - lon1).to_radians();
+    let a = (dlat / 2.0).sin().powi(2)
+        + lat1.to_radians().cos() * lat2.to_radians().cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * a.sqrt().asin()
+}
+
+// spherical web mercator, the plane the r-trees work in
+// it's conformal, so around any point a metre east and a metre north are the same length
+// and nearest neighbours come out the same as on the ground; raw degrees would make a
+// degree of latitude count ~40% more than it should at Ontario latitudes
+// lengths in this plane are stretched by 1 / cos(lat), see mercator_scale
+const MERCATOR_MAX_LAT: f64 = 85.051_128_78;
+
+pub fn to_mercator((lat, lon): Coord) -> [f64; 2] {
+    let lat = lat.clamp(-MERCATOR_MAX_LAT, MERCATOR_MAX_LAT).to_radians();
+    [
+        EARTH_RADIUS_M * lon.to_radians(),
+        EARTH_RADIUS_M * (std::f64::consts::FRAC_PI_4 + lat / 2.0).tan().ln(),
+    ]
+}
+
+pub fn from_mercator([x, y]: [f64; 2]) -> Coord {
+    let lat = 2.0 * (y / EARTH_RADIUS_M).exp().atan() - std::f64::consts::FRAC_PI_2;
+    (lat.to_degrees(), (x / EARTH_RADIUS_M).to_degrees())
+}
+
+// mercator units per metre on the ground at `lat`
+pub fn mercator_scale(lat: f64) -> f64 {
+    1.0 / lat
+        .clamp(-MERCATOR_MAX_LAT, MERCATOR_MAX_LAT)
+        .to_radians()
+        .cos()
+}
+
+// corners of a degree box that covers every point within `radius_m` of `center`, for
+// narrowing down candidates before measuring them with haversine_m
+pub fn box_around((lat, lon): Coord, radius_m: f64) -> (Coord, Coord) {
+    let dlat = (radius_m / EARTH_RADIUS_M).to_degrees();
+    let dlon = dlat / lat.to_radians().cos().max(0.01);
+    ((lat - dlat, lon - dlon), (lat + dlat, lon + dlon))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mercator_round_trip() {
+        for coord in [(0.0, 0.0), (44.38, -79.69), (-33.86, 151.21), (60.0, 179.9)] {
+            let (lat, lon) = from_mercator(to_mercator(coord));
+            assert!(
+                (lat - coord.0).abs() < 1e-9,
+                "{:?} came back as {}",
+                coord,
+                lat
+            );
+            assert!(
+                (lon - coord.1).abs() < 1e-9,
+                "{:?} came back as {}",
+                coord,
+                lon
+            );
+        }
+    }
+
+    #[test]
+    fn mercator_scale_matches_ground_distance() {
+        // 100 m east of a point in Barrie, measured in the plane and scaled back
+        let a = (44.38, -79.69);
+        let b = (44.38, -79.69 + 100.0 / haversine_m(a, (44.38, -78.69)));
+        let [x1, y1] = to_mercator(a);
+        let [x2, y2] = to_mercator(b);
+        let plane = ((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt() / mercator_scale(a.0);
+        assert!((plane - haversine_m(a, b)).abs() < 0.1);
+    }
+
+    #[test]
+    fn interior_point_of_an_l_shape_is_inside() {
+        // an L whose area-weighted centroid falls in the notch
+        let ring = vec![
+            (44.0, -79.0),
+            (44.0, -78.99),
+            (44.001, -78.99),
+            (44.001, -78.999),
+            (44.01, -78.999),
+            (44.01, -79.0),
+            (44.0, -79.0),
+        ];
+        let points = area_points(&[Polygon {
+            outer: ring.clone(),
+            holes: Vec::new(),
+        }])
+        .unwrap();
+        assert!(!point_in_ring(points.centroid, &ring));
+        assert!(point_in_ring(points.interior, &ring));
+    }
+
+    #[test]
+    fn sliver_pole_search_is_bounded() {
+        // 5 degrees long and 1e-7 degrees wide, the grid starts at the precision instead
+        let ring = vec![
+            (44.0, -80.0),
+            (44.0, -75.0),
+            (44.0000001, -75.0),
+            (44.0000001, -80.0),
+            (44.0, -80.0),
+        ];
+        let start = std::time::Instant::now();
+        let points = area_points(&[Polygon {
+            outer: ring,
+            holes: Vec::new(),
+        }]);
+        assert!(points.is_some());
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+    }
+}
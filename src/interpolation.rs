@@ -0,0 +1,209 @@
+// addr:interpolation ways, house numbers synthesized between the address nodes along them
+use crate::geometry::Coord;
+use crate::Address;
+use std::collections::{HashMap, HashSet};
+
+// bigger gaps between two address nodes are almost always a typo in a house number
+const MAX_INTERPOLATED_PER_SEGMENT: i64 = 1000;
+
+// an interpolation way waiting for the address nodes along it
+pub struct PendingInterpolation {
+    pub way_id: i64,
+    pub step: i64,
+    // addr:street on the way itself, used when the end nodes don't have one
+    pub street: String,
+    pub nodes: Vec<(i64, Coord)>,
+}
+
+// even and odd step by two, all by one; alphabetic and numeric steps are not supported
+pub fn interpolation_step(tags: &HashMap<String, String>) -> Option<i64> {
+    match tags.get("addr:interpolation")?.as_str() {
+        "even" | "odd" => Some(2),
+        "all" => Some(1),
+        _ => None,
+    }
+}
+
+// plain numbers only, "12A" or "12-14" can't be interpolated
+fn parse_housenumber(housenumber: &str) -> Option<i64> {
+    let number: i64 = housenumber.trim().parse().ok()?;
+    (number > 0).then_some(number)
+}
+
+// fills in the numbers between each pair of consecutive address nodes on the way
+// `node_addresses` maps node ids to the address rows found on those nodes
+// every number is produced once per way, the first segment to reach it wins, so ways
+// whose anchors repeat (a loop from 2 round to 2) don't hand out the same address twice
+pub fn interpolate(
+    line: &PendingInterpolation,
+    node_addresses: &HashMap<i64, &Address>,
+) -> Vec<Address> {
+    let mut interpolated = Vec::new();
+    let mut produced = HashSet::new();
+
+    let anchors: Vec<(usize, &Address, i64)> = line
+        .nodes
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, (node_id, _))| {
+            let address = node_addresses.get(node_id)?;
+            Some((idx, *address, parse_housenumber(&address.housenumber)?))
+        })
+        .collect();
+
+    for pair in anchors.windows(2) {
+        let (start_idx, start, start_number) = pair[0];
+        let (end_idx, end, end_number) = pair[1];
+
+        let span = end_number - start_number;
+        // even/odd ends have to share a parity, otherwise the tagging is broken
+        if span == 0 || span.abs() > MAX_INTERPOLATED_PER_SEGMENT || span % line.step != 0 {
+            continue;
+        }
+
+        let street = [&start.street, &end.street, &line.street]
+            .into_iter()
+            .find(|s| !s.is_empty())
+            .cloned()
+            .unwrap_or_default();
+        if street.is_empty() {
+            continue;
+        }
+
+        let segment: Vec<Coord> = line.nodes[start_idx..=end_idx]
+            .iter()
+            .map(|(_, coord)| *coord)
+            .collect();
+        let step = line.step * span.signum();
+        let mut number = start_number + step;
+        while number != end_number {
+            if !produced.insert(number) {
+                number += step;
+                continue;
+            }
+            let fraction = (number - start_number) as f64 / span as f64;
+            let (lat, lon) = point_along(&segment, fraction);
+            let mut address = Address {
+                id: line.way_id,
+                osm_type: "way".to_string(),
+                housenumber: number.to_string(),
+                street: street.clone(),
+                city: pick(&start.city, &end.city),
+                postcode: pick(&start.postcode, &end.postcode),
+                suburb: pick(&start.suburb, &end.suburb),
+                place: pick(&start.place, &end.place),
+                latitude: lat,
+                longitude: lon,
+                full_address: String::new(),
+                interpolated: true,
+                source_way_id: Some(line.way_id),
+                municipality: String::new(),
+                county: String::new(),
+                province: String::new(),
+                tags: None,
+            };
+            address.full_address = crate::format_full_address(&address);
+            interpolated.push(address);
+            number += step;
+        }
+    }
+
+    interpolated
+}
+
+// the start node's value unless it's missing there
+fn pick(start: &str, end: &str) -> String {
+    if start.is_empty() { end } else { start }.to_string()
+}
+
+// the point `fraction` of the way along a polyline, measured in a local equirectangular
+// projection so the spacing is even on the ground
+fn point_along(line: &[Coord], fraction: f64) -> Coord {
+    let scale = line[0].0.to_radians().cos();
+    let length = |a: Coord, b: Coord| ((a.0 - b.0).powi(2) + ((a.1 - b.1) * scale).powi(2)).sqrt();
+
+    let total: f64 = line.windows(2).map(|p| length(p[0], p[1])).sum();
+    let mut remaining = total * fraction;
+    for pair in line.windows(2) {
+        let piece = length(pair[0], pair[1]);
+        if piece > 0.0 && remaining <= piece {
+            let t = remaining / piece;
+            return (
+                pair[0].0 + (pair[1].0 - pair[0].0) * t,
+                pair[0].1 + (pair[1].1 - pair[0].1) * t,
+            );
+        }
+        remaining -= piece;
+    }
+    line[line.len() - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn anchor(housenumber: &str) -> Address {
+        Address {
+            id: 0,
+            osm_type: "node".to_string(),
+            housenumber: housenumber.to_string(),
+            street: "Bayfield Street".to_string(),
+            city: "Barrie".to_string(),
+            postcode: String::new(),
+            suburb: String::new(),
+            place: String::new(),
+            latitude: 0.0,
+            longitude: 0.0,
+            full_address: String::new(),
+            interpolated: false,
+            source_way_id: None,
+            municipality: String::new(),
+            county: String::new(),
+            province: String::new(),
+            tags: None,
+        }
+    }
+
+    // a way through nodes 1, 2, 3... a tenth of a millidegree apart, with the given
+    // house numbers on its nodes in order
+    fn numbers_along(step: i64, housenumbers: &[&str]) -> Vec<String> {
+        let anchors: Vec<Address> = housenumbers.iter().map(|n| anchor(n)).collect();
+        let line = PendingInterpolation {
+            way_id: 100,
+            step,
+            street: String::new(),
+            nodes: (0..anchors.len())
+                .map(|i| (i as i64 + 1, (44.38, -79.69 + i as f64 * 0.0001)))
+                .collect(),
+        };
+        let node_addresses: HashMap<i64, &Address> = anchors
+            .iter()
+            .enumerate()
+            .map(|(i, a)| (i as i64 + 1, a))
+            .collect();
+        interpolate(&line, &node_addresses)
+            .into_iter()
+            .map(|a| a.housenumber)
+            .collect()
+    }
+
+    #[test]
+    fn numbers_between_anchors() {
+        assert_eq!(numbers_along(2, &["2", "10"]), ["4", "6", "8"]);
+        assert_eq!(numbers_along(2, &["9", "3"]), ["7", "5"]);
+        assert_eq!(numbers_along(1, &["1", "4"]), ["2", "3"]);
+    }
+
+    #[test]
+    fn parity_mismatch_is_skipped() {
+        assert!(numbers_along(2, &["2", "9"]).is_empty());
+        // only the broken segment is dropped
+        assert_eq!(numbers_along(2, &["2", "9", "13"]), ["11"]);
+    }
+
+    #[test]
+    fn repeated_anchor_numbers_are_produced_once() {
+        assert_eq!(numbers_along(2, &["2", "10", "2"]), ["4", "6", "8"]);
+        assert_eq!(numbers_along(2, &["2", "10", "4"]), ["4", "6", "8"]);
+    }
+}
@@ -1,10 +1,46 @@
-use osmpbf::{Element, ElementReader};
+mod admin;
+mod categories;
+mod cli;
+mod details;
+mod geocode;
+mod geometry;
+mod interpolation;
+mod names;
+mod node_store;
+mod opening_hours;
+mod places;
+mod reader;
+mod roads;
+mod server;
+mod tags;
+
+use admin::{AdminArea, AdminIndex};
+use categories::{CategoryMapping, CategoryMatch};
+use clap::Parser;
+use cli::{
+    BoundingBox, Cli, Command, ExtractArgs, InspectArgs, OpenAtArgs, OutputFormat, QueryArgs,
+    ResultFormat, ReverseArgs, SearchArgs, ServeArgs, ValidateArgs,
+};
+use details::PoiDetails;
+use geometry::{AreaPoints, Coord, Geometry, GeometryFormat, Polygon};
+use interpolation::PendingInterpolation;
+use names::NameVariant;
+use node_store::NodeStore;
+use osmpbf::{Element, ElementReader, PrimitiveBlock, RelMemberType};
+use places::{Locality, Place, PlaceIndex};
+use rayon::iter::{IntoParallelRefMutIterator, ParallelIterator};
+use roads::RoadSegment;
 use rstar::RTree;
+use rusqlite::types::{ToSql, ToSqlOutput, ValueRef};
 use rusqlite::{params, Connection, Result as SqlResult};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::env;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use std::time::Instant;
+use tags::TagFilter;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 struct PointOfInterest {
@@ -18,11 +54,45 @@ struct PointOfInterest {
     city: String,
     street: String,
     osm_type: String,
+    // every matching category, the first one is the primary category above
+    categories: Vec<CategoryMatch>,
+    // every name, translation and alternative name the poi is known by
+    names: Vec<NameVariant>,
+    // area pois only: area-weighted centroid and a point guaranteed to be inside the area
+    centroid_latitude: Option<f64>,
+    centroid_longitude: Option<f64>,
+    interior_latitude: Option<f64>,
+    interior_longitude: Option<f64>,
+    // way and relation pois only: extent of the element
+    min_lat: Option<f64>,
+    min_lon: Option<f64>,
+    max_lat: Option<f64>,
+    max_lon: Option<f64>,
+    // area outline, only with --geometry
+    geometry: Option<Geometry>,
+    // the assembled outline at full precision, only kept for the geojson formats
+    #[serde(skip)]
+    outline: Vec<Polygon>,
+    // where housenumber, street and city came from: "tagged" from the poi's own tags,
+    // "inferred" from the nearest road, address or place, "mixed" when some of each, None
+    // without any; `address_distance_m` is how far the farthest inferred field came from
+    address_source: Option<String>,
+    address_distance_m: Option<f64>,
+    // from the admin boundaries the poi falls in
+    municipality: String,
+    county: String,
+    province: String,
+    // opening hours, contact details and brand
+    #[serde(flatten)]
+    details: PoiDetails,
+    // raw osm tags, only with --keep-tags
+    tags: Option<BTreeMap<String, String>>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 struct Address {
     id: i64,
+    osm_type: String,
     housenumber: String,
     street: String,
     city: String,
@@ -32,6 +102,15 @@ struct Address {
     latitude: f64,
     longitude: f64,
     full_address: String,
+    // synthesized along an addr:interpolation way rather than mapped
+    interpolated: bool,
+    source_way_id: Option<i64>,
+    // from the admin boundaries the address falls in
+    municipality: String,
+    county: String,
+    province: String,
+    // raw osm tags, only with --keep-tags
+    tags: Option<BTreeMap<String, String>>,
 }
 
 #[derive(Clone, Debug)]
@@ -39,6 +118,7 @@ struct AddressPoint {
     housenumber: String,
     street: String,
     city: String,
+    // web mercator, see geometry::to_mercator
     point: [f64; 2],
 }
 
@@ -58,122 +138,164 @@ impl rstar::PointDistance for AddressPoint {
     }
 }
 
-// category mapping
-fn get_category_mapping() -> HashMap<String, HashMap<String, String>> {
-    let mut category_map: HashMap<String, HashMap<String, String>> = HashMap::new();
-
-    // amenity mappings
-    let mut amenity_map = HashMap::new();
-    // food and dining places
-    amenity_map.insert("restaurant".to_string(), "food".to_string());
-    amenity_map.insert("cafe".to_string(), "food".to_string());
-    amenity_map.insert("fast_food".to_string(), "food".to_string());
-    amenity_map.insert("bar".to_string(), "food".to_string());
-    amenity_map.insert("pub".to_string(), "food".to_string());
-    amenity_map.insert("food_court".to_string(), "food".to_string());
-    amenity_map.insert("ice_cream".to_string(), "food".to_string());
-    amenity_map.insert("biergarten".to_string(), "food".to_string());
-
-    // entertainment spots
-    amenity_map.insert("cinema".to_string(), "entertainment".to_string());
-    amenity_map.insert("theatre".to_string(), "entertainment".to_string());
-    amenity_map.insert("nightclub".to_string(), "entertainment".to_string());
-    amenity_map.insert("casino".to_string(), "entertainment".to_string());
-    amenity_map.insert("arts_centre".to_string(), "entertainment".to_string());
-    amenity_map.insert("community_centre".to_string(), "entertainment".to_string());
-
-    // healthcare facilities
-    amenity_map.insert("hospital".to_string(), "healthcare".to_string());
-    amenity_map.insert("clinic".to_string(), "healthcare".to_string());
-    amenity_map.insert("doctors".to_string(), "healthcare".to_string());
-    amenity_map.insert("dentist".to_string(), "healthcare".to_string());
-    amenity_map.insert("pharmacy".to_string(), "healthcare".to_string());
-    amenity_map.insert("veterinary".to_string(), "healthcare".to_string());
-
-    // financial services
-    amenity_map.insert("bank".to_string(), "financial".to_string());
-    amenity_map.insert("atm".to_string(), "financial".to_string());
-    amenity_map.insert("bureau_de_change".to_string(), "financial".to_string());
-
-    // transportation stuff
-    amenity_map.insert("fuel".to_string(), "transportation".to_string());
-    amenity_map.insert("parking".to_string(), "transportation".to_string());
-    amenity_map.insert("car_rental".to_string(), "transportation".to_string());
-    amenity_map.insert("bicycle_rental".to_string(), "transportation".to_string());
-    amenity_map.insert("bus_station".to_string(), "transportation".to_string());
-    amenity_map.insert("taxi".to_string(), "transportation".to_string());
-
-    // education places
-    amenity_map.insert("school".to_string(), "education".to_string());
-    amenity_map.insert("university".to_string(), "education".to_string());
-    amenity_map.insert("college".to_string(), "education".to_string());
-    amenity_map.insert("library".to_string(), "education".to_string());
-    amenity_map.insert("kindergarten".to_string(), "education".to_string());
-    category_map.insert("amenity".to_string(), amenity_map);
-
-    // shop mappings
-    let mut shop_map = HashMap::new();
-    shop_map.insert("supermarket".to_string(), "shopping".to_string());
-    shop_map.insert("convenience".to_string(), "shopping".to_string());
-    shop_map.insert("clothes".to_string(), "shopping".to_string());
-    shop_map.insert("mall".to_string(), "shopping".to_string());
-    shop_map.insert("department_store".to_string(), "shopping".to_string());
-    shop_map.insert("electronics".to_string(), "shopping".to_string());
-    shop_map.insert("furniture".to_string(), "shopping".to_string());
-    shop_map.insert("books".to_string(), "shopping".to_string());
-    shop_map.insert("bakery".to_string(), "shopping".to_string());
-    shop_map.insert("butcher".to_string(), "shopping".to_string());
-    shop_map.insert("florist".to_string(), "shopping".to_string());
-    shop_map.insert("hardware".to_string(), "shopping".to_string());
-    category_map.insert("shop".to_string(), shop_map);
-
-    // tourism mappings
-    let mut tourism_map = HashMap::new();
-    tourism_map.insert("hotel".to_string(), "accommodation".to_string());
-    tourism_map.insert("motel".to_string(), "accommodation".to_string());
-    tourism_map.insert("hostel".to_string(), "accommodation".to_string());
-    tourism_map.insert("guest_house".to_string(), "accommodation".to_string());
-    tourism_map.insert("attraction".to_string(), "entertainment".to_string());
-    tourism_map.insert("museum".to_string(), "entertainment".to_string());
-    tourism_map.insert("gallery".to_string(), "entertainment".to_string());
-    tourism_map.insert("viewpoint".to_string(), "entertainment".to_string());
-    category_map.insert("tourism".to_string(), tourism_map);
-
-    // leisure mappings
-    let mut leisure_map = HashMap::new();
-    leisure_map.insert("park".to_string(), "entertainment".to_string());
-    leisure_map.insert("sports_centre".to_string(), "entertainment".to_string());
-    leisure_map.insert("playground".to_string(), "entertainment".to_string());
-    leisure_map.insert("stadium".to_string(), "entertainment".to_string());
-    leisure_map.insert("swimming_pool".to_string(), "entertainment".to_string());
-    leisure_map.insert("fitness_centre".to_string(), "entertainment".to_string());
-    leisure_map.insert("golf_course".to_string(), "entertainment".to_string());
-    category_map.insert("leisure".to_string(), leisure_map);
-
-    // office mappings
-    let mut office_map = HashMap::new();
-    office_map.insert(
-        "educational_institution".to_string(),
-        "education".to_string(),
-    );
-    office_map.insert("university".to_string(), "education".to_string());
-    category_map.insert("office".to_string(), office_map);
+// elements with these tags become rows in the addresses table
+// interpolation ways carry addr:street too, but they stand for a range, not one address
+fn has_address_tags(tags: &HashMap<String, String>) -> bool {
+    (tags.contains_key("addr:housenumber") || tags.contains_key("addr:street"))
+        && !tags.contains_key("addr:interpolation")
+}
+
+// multipolygon relations we turn into pois or addresses, the same rule pass 1 uses to pick
+// member ways
+fn is_extracted_multipolygon(
+    tags: &HashMap<String, String>,
+    category_map: &CategoryMapping,
+) -> bool {
+    tags.get("type").map(String::as_str) == Some("multipolygon")
+        && (category_map.is_match(tags) || has_address_tags(tags))
+}
+
+// old-style multipolygons often leave the role empty, those members are outers too
+fn is_outer_role(role: &str) -> bool {
+    role == "outer" || role.is_empty()
+}
+
+fn is_inner_role(role: &str) -> bool {
+    role == "inner"
+}
+
+// ways whose node coordinates pass 2 actually looks up, pois, addressed buildings,
+// interpolation lines and, when enrichment needs them, named streets
+fn is_extracted_way(
+    tags: &HashMap<String, String>,
+    category_map: &CategoryMapping,
+    roads: bool,
+) -> bool {
+    category_map.is_match(tags)
+        || has_address_tags(tags)
+        || interpolation::interpolation_step(tags).is_some()
+        || (roads && roads::road_name(tags).is_some())
+}
+
+// adds the outer and inner member ways of an extracted multipolygon or admin boundary
+// to `way_ids`
+fn collect_member_way_ids(
+    relation: &osmpbf::Relation,
+    category_map: &CategoryMapping,
+    way_ids: &mut HashSet<i64>,
+) {
+    let tags: HashMap<String, String> = relation
+        .tags()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+    if admin::admin_level(&tags).is_some() || is_extracted_multipolygon(&tags, category_map) {
+        for member in relation.members() {
+            let role = member.role().unwrap_or("");
+            if member.member_type == RelMemberType::Way
+                && (is_outer_role(role) || is_inner_role(role))
+            {
+                way_ids.insert(member.member_id);
+            }
+        }
+    }
+}
+
+// node and way ids found by the pass 0 scan of one block; node and extracted way ids are
+// plain vectors, sorted and deduplicated once the scan is done, a hash set of every
+// referenced node would take several times the memory
+#[derive(Default)]
+struct ReferenceScan {
+    referenced: Vec<i64>,
+    member_way_ids: HashSet<i64>,
+    extracted_way_ids: Vec<i64>,
+}
+
+impl ReferenceScan {
+    fn merge(mut self, other: ReferenceScan) -> ReferenceScan {
+        self.referenced.extend(other.referenced);
+        self.member_way_ids.extend(other.member_way_ids);
+        self.extracted_way_ids.extend(other.extracted_way_ids);
+        self
+    }
+}
+
+// pre-pass for --only-referenced-nodes, finds every node an extracted way or multipolygon uses
+// member ways come before their relations in the file, so once the relations are known
+// a second read picks up the refs of member ways that aren't extracted themselves
+// returns the node ids sorted, for binary search
+fn collect_referenced_nodes(
+    pbf_path: &Path,
+    category_map: &CategoryMapping,
+    roads: bool,
+) -> Result<Vec<i64>, Box<dyn std::error::Error>> {
+    let scan = reader::par_map_blocks(
+        pbf_path,
+        "Scanned",
+        ReferenceScan::default,
+        |block| {
+            let mut scan = ReferenceScan::default();
+            for element in block.elements() {
+                match element {
+                    Element::Way(way) => {
+                        let tags: HashMap<String, String> = way
+                            .tags()
+                            .map(|(k, v)| (k.to_string(), v.to_string()))
+                            .collect();
+                        if is_extracted_way(&tags, category_map, roads) {
+                            scan.referenced.extend(way.refs());
+                            scan.extracted_way_ids.push(way.id());
+                        }
+                    }
+                    Element::Relation(relation) => {
+                        collect_member_way_ids(&relation, category_map, &mut scan.member_way_ids);
+                    }
+                    _ => {}
+                }
+            }
+            scan
+        },
+        ReferenceScan::merge,
+    )?;
 
-    // education key mappings
-    let mut education_map = HashMap::new();
-    education_map.insert("school".to_string(), "education".to_string());
-    education_map.insert("university".to_string(), "education".to_string());
-    education_map.insert("college".to_string(), "education".to_string());
-    category_map.insert("education".to_string(), education_map);
+    let mut referenced = scan.referenced;
+    let mut extracted_way_ids = scan.extracted_way_ids;
+    extracted_way_ids.sort_unstable();
+    let mut member_way_ids = scan.member_way_ids;
+    member_way_ids.retain(|id| extracted_way_ids.binary_search(id).is_err());
+    detail!(
+        "  {} poi and address ways, {} other multipolygon member ways",
+        extracted_way_ids.len(),
+        member_way_ids.len()
+    );
 
-    // building mappings
-    let mut building_map = HashMap::new();
-    building_map.insert("college".to_string(), "education".to_string());
-    building_map.insert("university".to_string(), "education".to_string());
-    building_map.insert("school".to_string(), "education".to_string());
-    category_map.insert("building".to_string(), building_map);
+    if !member_way_ids.is_empty() {
+        let member_refs = reader::par_map_blocks(
+            pbf_path,
+            "Scanned",
+            Vec::new,
+            |block| {
+                let mut refs = Vec::new();
+                for group in block.groups() {
+                    for way in group.ways() {
+                        if member_way_ids.contains(&way.id()) {
+                            refs.extend(way.refs());
+                        }
+                    }
+                }
+                refs
+            },
+            |mut a, b| {
+                a.extend(b);
+                a
+            },
+        )?;
+        referenced.extend(member_refs);
+    }
 
-    category_map
+    referenced.sort_unstable();
+    referenced.dedup();
+    Ok(referenced)
 }
 
 fn process_node_tags(
@@ -181,154 +303,411 @@ fn process_node_tags(
     lat: f64,
     lon: f64,
     tags: HashMap<String, String>,
-    category_map: &HashMap<String, HashMap<String, String>>,
-    pois: &mut Vec<PointOfInterest>,
-    addresses: &mut Vec<Address>,
-    address_index: &mut RTree<AddressPoint>,
+    ctx: &Pass2Context,
+    out: &mut Pass2Output,
 ) {
     // checking for points of interest
-    let mut category: Option<String> = None;
-    let mut subcategory: Option<String> = None;
-
-    for (tag_key, value_map) in category_map.iter() {
-        if let Some(tag_value) = tags.get(tag_key) {
-            if let Some(cat) = value_map.get(tag_value) {
-                category = Some(cat.clone());
-                subcategory = Some(tag_value.clone());
-                break;
-            }
-        }
-    }
-
-    if let Some(cat) = category {
-        pois.push(PointOfInterest {
+    let categories = ctx.category_map.classify(&tags);
+    if let Some(primary) = categories.first() {
+        let (housenumber, street, city) = tagged_address(&tags);
+        out.pois.push(PointOfInterest {
             id: node_id,
-            name: tags
-                .get("name")
-                .cloned()
-                .unwrap_or_else(|| "Unnamed".to_string()),
-            category: cat,
-            subcategory: subcategory.unwrap_or_default(),
+            name: names::primary_name(&tags).unwrap_or_else(|| "Unnamed".to_string()),
+            category: primary.category.clone(),
+            subcategory: primary.subcategory.clone(),
             latitude: lat,
             longitude: lon,
-            housenumber: tags.get("addr:housenumber").cloned().unwrap_or_default(),
-            city: tags.get("addr:city").cloned().unwrap_or_default(),
-            street: tags.get("addr:street").cloned().unwrap_or_default(),
+            address_source: tagged_source(&housenumber, &street, &city),
+            address_distance_m: None,
+            housenumber,
+            city,
+            street,
             osm_type: "node".to_string(),
+            categories,
+            names: names::name_variants(&tags),
+            centroid_latitude: None,
+            centroid_longitude: None,
+            interior_latitude: None,
+            interior_longitude: None,
+            min_lat: None,
+            min_lon: None,
+            max_lat: None,
+            max_lon: None,
+            geometry: None,
+            outline: Vec::new(),
+            municipality: String::new(),
+            county: String::new(),
+            province: String::new(),
+            details: PoiDetails::from_tags(&tags),
+            tags: ctx.kept_tags(&tags),
         });
     }
 
     // checking for addresses
-    if tags.contains_key("addr:housenumber") || tags.contains_key("addr:street") {
-        let housenumber = tags.get("addr:housenumber").cloned().unwrap_or_default();
-        let street = tags.get("addr:street").cloned().unwrap_or_default();
-        let city = tags.get("addr:city").cloned().unwrap_or_default();
-        let postcode = tags.get("addr:postcode").cloned().unwrap_or_default();
-        let suburb = tags.get("addr:suburb").cloned().unwrap_or_default();
-        let place = tags.get("addr:place").cloned().unwrap_or_default();
-
-        let mut full_addr = String::new();
-        if !housenumber.is_empty() {
-            full_addr.push_str(&format!("{} ", housenumber));
-        }
-        if !street.is_empty() {
-            full_addr.push_str(&format!("{}, ", street));
-        }
-        if !place.is_empty() {
-            full_addr.push_str(&format!("{}, ", place));
-        }
-        if !suburb.is_empty() {
-            full_addr.push_str(&format!("{}, ", suburb));
-        }
-        if !city.is_empty() {
-            full_addr.push_str(&format!("{} ", city));
-        }
-        if !postcode.is_empty() {
-            full_addr.push_str(&postcode);
-        }
+    out.addresses.extend(address_from_tags(
+        node_id,
+        "node",
+        (lat, lon),
+        &tags,
+        ctx.tag_filter.as_ref(),
+    ));
+
+    // checking for named places, kept for locality inference
+    out.places
+        .extend(places::place_from_tags(node_id, (lat, lon), &tags));
+}
 
-        addresses.push(Address {
-            id: node_id,
-            housenumber: housenumber.clone(),
-            street: street.clone(),
-            city: city.clone(),
-            postcode,
-            suburb,
-            place,
-            latitude: lat,
-            longitude: lon,
-            full_address: full_addr.trim().to_string(),
-        });
+// an address row for any element carrying addr:housenumber or addr:street
+fn address_from_tags(
+    id: i64,
+    osm_type: &str,
+    (lat, lon): Coord,
+    tags: &HashMap<String, String>,
+    tag_filter: Option<&TagFilter>,
+) -> Option<Address> {
+    if !has_address_tags(tags) {
+        return None;
+    }
 
-        // we add to spatial index if we have meaningful address data
-        if !street.is_empty() && !housenumber.is_empty() {
-            address_index.insert(AddressPoint {
-                housenumber,
-                street,
-                city,
-                point: [lon, lat],
-            });
-        }
+    let housenumber = tags.get("addr:housenumber").cloned().unwrap_or_default();
+    let street = tags.get("addr:street").cloned().unwrap_or_default();
+    let city = tags.get("addr:city").cloned().unwrap_or_default();
+    let postcode = tags.get("addr:postcode").cloned().unwrap_or_default();
+    let suburb = tags.get("addr:suburb").cloned().unwrap_or_default();
+    let place = tags.get("addr:place").cloned().unwrap_or_default();
+
+    let mut address = Address {
+        id,
+        osm_type: osm_type.to_string(),
+        housenumber,
+        street,
+        city,
+        postcode,
+        suburb,
+        place,
+        latitude: lat,
+        longitude: lon,
+        full_address: String::new(),
+        interpolated: false,
+        source_way_id: None,
+        municipality: String::new(),
+        county: String::new(),
+        province: String::new(),
+        tags: tag_filter.map(|f| f.apply(tags)),
+    };
+    address.full_address = format_full_address(&address);
+    Some(address)
+}
+
+fn format_full_address(address: &Address) -> String {
+    let mut full_addr = String::new();
+    if !address.housenumber.is_empty() {
+        full_addr.push_str(&format!("{} ", address.housenumber));
+    }
+    if !address.street.is_empty() {
+        full_addr.push_str(&format!("{}, ", address.street));
+    }
+    if !address.place.is_empty() {
+        full_addr.push_str(&format!("{}, ", address.place));
+    }
+    if !address.suburb.is_empty() {
+        full_addr.push_str(&format!("{}, ", address.suburb));
+    }
+    if !address.city.is_empty() {
+        full_addr.push_str(&format!("{} ", address.city));
     }
+    if !address.postcode.is_empty() {
+        full_addr.push_str(&address.postcode);
+    }
+    full_addr.trim().to_string()
+}
+
+// fills in municipality, county and province from the boundaries each point falls in
+fn assign_admin_areas(
+    pois: &mut [PointOfInterest],
+    addresses: &mut [Address],
+    admin_areas: Vec<AdminArea>,
+) {
+    progress!("Assigning administrative areas...");
+    let start = Instant::now();
+
+    let index = AdminIndex::new(admin_areas);
+    pois.par_iter_mut().for_each(|poi| {
+        let names = index.lookup(poi.latitude, poi.longitude);
+        poi.municipality = names.municipality;
+        poi.county = names.county;
+        poi.province = names.province;
+    });
+    addresses.par_iter_mut().for_each(|addr| {
+        let names = index.lookup(addr.latitude, addr.longitude);
+        addr.municipality = names.municipality;
+        addr.county = names.county;
+        addr.province = names.province;
+    });
+
+    progress!(
+        "  ✓ Placed {} POIs and {} addresses in {} boundaries in {:.2?}",
+        pois.len(),
+        addresses.len(),
+        index.len(),
+        start.elapsed()
+    );
+}
+
+// fills empty city, suburb and place fields from the nearest fitting place node
+fn fill_address_localities(addresses: &mut [Address], place_index: &PlaceIndex) {
+    progress!("Inferring address localities from place nodes...");
+    let start = Instant::now();
+
+    let filled: usize = addresses
+        .par_iter_mut()
+        .map(|addr| {
+            let mut changed = false;
+            for (field, locality) in [
+                (&mut addr.city, Locality::City),
+                (&mut addr.suburb, Locality::Suburb),
+                (&mut addr.place, Locality::Place),
+            ] {
+                if field.is_empty() {
+                    if let Some(place) = place_index.best(addr.latitude, addr.longitude, locality) {
+                        *field = place.name.clone();
+                        changed = true;
+                    }
+                }
+            }
+            if changed {
+                addr.full_address = format_full_address(addr);
+            }
+            changed as usize
+        })
+        .sum();
+
+    progress!(
+        "  ✓ Filled in localities for {} addresses in {:.2?}",
+        filled,
+        start.elapsed()
+    );
 }
 
+// pois only have a city field, filled when neither the tags nor enrichment gave one
+fn fill_poi_localities(pois: &mut [PointOfInterest], place_index: &PlaceIndex) {
+    let filled: usize = pois
+        .par_iter_mut()
+        .filter(|poi| poi.city.is_empty())
+        .map(
+            |poi| match place_index.best(poi.latitude, poi.longitude, Locality::City) {
+                Some(place) => {
+                    poi.city = place.name.clone();
+                    poi.mark_inferred(geometry::haversine_m(
+                        (poi.latitude, poi.longitude),
+                        (place.latitude, place.longitude),
+                    ));
+                    1
+                }
+                None => 0,
+            },
+        )
+        .sum();
+    progress!("  ✓ Filled in the city of {} POIs from place nodes", filled);
+}
+
+// spatial index over the addresses, built once pass 2 has found all of them
+fn build_address_index(addresses: &[Address]) -> RTree<AddressPoint> {
+    // we only index addresses with meaningful address data
+    let points = addresses
+        .iter()
+        .filter(|a| !a.street.is_empty() && !a.housenumber.is_empty())
+        .map(|a| AddressPoint {
+            housenumber: a.housenumber.clone(),
+            street: a.street.clone(),
+            city: a.city.clone(),
+            point: geometry::to_mercator((a.latitude, a.longitude)),
+        })
+        .collect();
+    RTree::bulk_load(points)
+}
+
+// nearest address point and its distance in metres, if it's within `max_distance_m`
 fn find_nearest_address(
     index: &RTree<AddressPoint>,
     lat: f64,
     lon: f64,
-) -> Option<(String, String, String)> {
-    let nearest = index.nearest_neighbor(&[lon, lat])?;
-    Some((
-        nearest.housenumber.clone(),
-        nearest.street.clone(),
-        nearest.city.clone(),
-    ))
+    max_distance_m: f64,
+) -> Option<(&AddressPoint, f64)> {
+    let nearest = index.nearest_neighbor(&geometry::to_mercator((lat, lon)))?;
+    let distance = geometry::haversine_m((lat, lon), geometry::from_mercator(nearest.point));
+    (distance <= max_distance_m).then_some((nearest, distance))
+}
+
+// name of the closest named road and its distance in metres, measured to the road itself
+// rather than its nodes
+fn find_nearest_road(
+    index: &RTree<RoadSegment>,
+    lat: f64,
+    lon: f64,
+    max_distance_m: f64,
+) -> Option<(&str, f64)> {
+    let mut candidates =
+        index.nearest_neighbor_iter_with_distance_2(&geometry::to_mercator((lat, lon)));
+    let (first, best) = candidates.next()?;
+    // roads meeting at a corner can be exactly as near, the lowest way id wins so the
+    // street doesn't depend on how the index was built
+    let nearest = candidates
+        .take_while(|(_, d)| *d <= best)
+        .map(|(segment, _)| segment)
+        .fold(first, |a, b| if b.key() < a.key() { b } else { a });
+    let distance = geometry::haversine_m((lat, lon), nearest.closest_point((lat, lon)));
+    (distance <= max_distance_m).then_some((&*nearest.name, distance))
+}
+
+// "tagged" when the element's own addr:* tags give a street or house number
+fn tagged_source(housenumber: &str, street: &str, city: &str) -> Option<String> {
+    [housenumber, street, city]
+        .iter()
+        .any(|field| !field.is_empty())
+        .then(|| "tagged".to_string())
+}
+
+impl PointOfInterest {
+    // records that one address field was filled in from something `distance` metres away
+    fn mark_inferred(&mut self, distance: f64) {
+        self.address_source = Some(match self.address_source.as_deref() {
+            Some("tagged") | Some("mixed") => "mixed".to_string(),
+            _ => "inferred".to_string(),
+        });
+        self.address_distance_m = Some(
+            self.address_distance_m
+                .map_or(distance, |d| d.max(distance)),
+        );
+    }
+}
+
+// housenumber, street and city from the element's own addr:* tags
+fn tagged_address(tags: &HashMap<String, String>) -> (String, String, String) {
+    (
+        tags.get("addr:housenumber").cloned().unwrap_or_default(),
+        tags.get("addr:street").cloned().unwrap_or_default(),
+        tags.get("addr:city").cloned().unwrap_or_default(),
+    )
+}
+
+// representative point and polygons of a multipolygon from its member ways
+fn assemble_multipolygon(
+    outer_ways: Vec<Vec<Coord>>,
+    inner_ways: Vec<Vec<Coord>>,
+) -> (Option<Coord>, Vec<Polygon>) {
+    let all_coords: Vec<Coord> = outer_ways.iter().flatten().copied().collect();
+    let outer_rings = geometry::assemble_rings(outer_ways);
+
+    // broken multipolygons (missing members at extract edges) still get a point
+    let point =
+        geometry::rings_centroid(&outer_rings).or_else(|| geometry::mean_point(&all_coords));
+    let polygons = geometry::build_polygons(outer_rings, geometry::assemble_rings(inner_ways));
+    (point, polygons)
 }
 
 fn enrich_pois_with_addresses(
-    pois: &mut Vec<PointOfInterest>,
+    pois: &mut [PointOfInterest],
     address_index: &RTree<AddressPoint>,
+    road_index: &RTree<RoadSegment>,
+    max_distance_m: f64,
 ) {
-    println!("Enriching POIs with nearest addresses...");
+    progress!(
+        "Enriching POIs with roads and addresses within {} m...",
+        max_distance_m
+    );
     let start = Instant::now();
     let mut enriched_count = 0;
 
     for poi in pois.iter_mut() {
         // only enrich if missing street or housenumber
-        if poi.street.is_empty() || poi.housenumber.is_empty() {
-            if let Some((nearest_num, nearest_street, nearest_city)) =
-                find_nearest_address(address_index, poi.latitude, poi.longitude)
-            {
-                poi.housenumber = nearest_num;
-                poi.street = nearest_street;
-                if poi.city.is_empty() {
-                    poi.city = nearest_city;
-                }
-                enriched_count += 1;
+        if !poi.street.is_empty() && !poi.housenumber.is_empty() {
+            continue;
+        }
+        let (lat, lon) = (poi.latitude, poi.longitude);
+        let nearest = find_nearest_address(address_index, lat, lon, max_distance_m);
+        let mut enriched = false;
+
+        // the nearest road is a better guess for the street than the nearest address,
+        // which can sit around the corner; without a road nearby we fall back to it
+        if poi.street.is_empty() {
+            let street = find_nearest_road(road_index, lat, lon, max_distance_m)
+                .map(|(name, distance)| (name.to_string(), distance))
+                .or_else(|| nearest.map(|(a, distance)| (a.street.clone(), distance)));
+            if let Some((street, distance)) = street {
+                poi.street = street;
+                poi.mark_inferred(distance);
+                enriched = true;
             }
         }
+        if let Some((nearest, distance)) = nearest {
+            // a house number only makes sense on the street it belongs to
+            if poi.housenumber.is_empty() && nearest.street.eq_ignore_ascii_case(&poi.street) {
+                poi.housenumber = nearest.housenumber.clone();
+                poi.mark_inferred(distance);
+                enriched = true;
+            }
+            if poi.city.is_empty() && !nearest.city.is_empty() {
+                poi.city = nearest.city.clone();
+                poi.mark_inferred(distance);
+                enriched = true;
+            }
+        }
+
+        if enriched {
+            enriched_count += 1;
+        }
     }
 
-    println!(
-        "  ✓ Enriched {} POIs with nearest addresses in {:.2?}",
+    progress!(
+        "  ✓ Enriched {} POIs with nearby roads and addresses in {:.2?}",
         enriched_count,
         start.elapsed()
     );
 }
 
+// the database is built next to the output under a .tmp name and renamed over it at the
+// end, so a re-run replaces the previous database instead of adding to it, and a failed
+// run leaves the previous one in place
 fn export_to_sqlite(
     pois: &[PointOfInterest],
     addresses: &[Address],
-    db_path: &str,
-) -> SqlResult<()> {
-    println!("Creating SQLite database at {}...", db_path);
+    places: &[Place],
+    db_path: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    progress!("Creating SQLite database at {}...", db_path.display());
+
+    let mut tmp_path = db_path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+
+    // left over from an interrupted run
+    if tmp_path.exists() {
+        std::fs::remove_file(&tmp_path)?;
+    }
+    if let Err(e) = write_sqlite(pois, addresses, places, &tmp_path) {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(e.into());
+    }
+    std::fs::rename(&tmp_path, db_path)?;
 
+    progress!("✓ SQLite database created successfully");
+    Ok(())
+}
+
+fn write_sqlite(
+    pois: &[PointOfInterest],
+    addresses: &[Address],
+    places: &[Place],
+    db_path: &Path,
+) -> SqlResult<()> {
     // creating the database connection
     let conn = Connection::open(db_path)?;
 
     // creating the pois table with indexes
     conn.execute(
         "CREATE TABLE IF NOT EXISTS pois (
+            poi_id INTEGER PRIMARY KEY,
             id INTEGER NOT NULL,
             name TEXT NOT NULL,
             category TEXT NOT NULL,
@@ -339,6 +718,28 @@ fn export_to_sqlite(
             street TEXT,
             city TEXT,
             osm_type TEXT NOT NULL,
+            centroid_latitude REAL,
+            centroid_longitude REAL,
+            interior_latitude REAL,
+            interior_longitude REAL,
+            min_lat REAL,
+            min_lon REAL,
+            max_lat REAL,
+            max_lon REAL,
+            geometry,
+            municipality TEXT,
+            county TEXT,
+            province TEXT,
+            address_source TEXT,
+            address_distance_m REAL,
+            opening_hours TEXT,
+            opening_hours_normalized TEXT,
+            phone TEXT,
+            website TEXT,
+            brand TEXT,
+            brand_wikidata TEXT,
+            operator TEXT,
+            tags TEXT,
             full_address TEXT GENERATED ALWAYS AS (
                 CASE
                     WHEN housenumber IS NOT NULL AND housenumber != '' AND street IS NOT NULL AND street != ''
@@ -350,7 +751,7 @@ fn export_to_sqlite(
                     ELSE ''
                 END
             ) STORED,
-            PRIMARY KEY (osm_type, id)
+            UNIQUE (osm_type, id)
         )",
         [],
     )?;
@@ -372,11 +773,55 @@ fn export_to_sqlite(
         "CREATE INDEX IF NOT EXISTS idx_poi_city ON pois(city COLLATE NOCASE)",
         [],
     )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_poi_brand ON pois(brand COLLATE NOCASE)",
+        [],
+    )?;
+
+    // every category a poi matched, rank 0 is the primary category stored on pois
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS poi_categories (
+            osm_type TEXT NOT NULL,
+            id INTEGER NOT NULL,
+            rank INTEGER NOT NULL,
+            category TEXT NOT NULL,
+            subcategory TEXT,
+            tag_key TEXT NOT NULL,
+            PRIMARY KEY (osm_type, id, rank)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_poi_categories_category ON poi_categories(category)",
+        [],
+    )?;
+
+    // every name variant of a poi, language is NULL for the untagged local name
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS poi_names (
+            osm_type TEXT NOT NULL,
+            id INTEGER NOT NULL,
+            kind TEXT NOT NULL,
+            language TEXT,
+            name TEXT NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_poi_names_name ON poi_names(name COLLATE NOCASE)",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_poi_names_poi ON poi_names(osm_type, id)",
+        [],
+    )?;
 
     // creating the addresses table
     conn.execute(
         "CREATE TABLE IF NOT EXISTS addresses (
-            id INTEGER PRIMARY KEY,
+            address_id INTEGER PRIMARY KEY,
+            id INTEGER NOT NULL,
+            osm_type TEXT NOT NULL,
             housenumber TEXT,
             street TEXT,
             city TEXT,
@@ -385,7 +830,14 @@ fn export_to_sqlite(
             place TEXT,
             latitude REAL NOT NULL,
             longitude REAL NOT NULL,
-            full_address TEXT
+            full_address TEXT,
+            interpolated INTEGER NOT NULL DEFAULT 0,
+            source_way_id INTEGER,
+            municipality TEXT,
+            county TEXT,
+            province TEXT,
+            tags TEXT,
+            UNIQUE (osm_type, id, housenumber)
         )",
         [],
     )?;
@@ -400,15 +852,47 @@ fn export_to_sqlite(
         [],
     )?;
 
-    println!("  Inserting {} POIs...", pois.len());
+    // creating the places table
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS places (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            place TEXT NOT NULL,
+            population INTEGER,
+            latitude REAL NOT NULL,
+            longitude REAL NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_places_name ON places(name COLLATE NOCASE)",
+        [],
+    )?;
+
+    progress!("  Inserting {} POIs...", pois.len());
 
     // starting a transaction for bulk insert to make it faster
     let tx = conn.unchecked_transaction()?;
 
     {
         let mut stmt = tx.prepare(
-            "INSERT INTO pois (id, name, category, subcategory, latitude, longitude, housenumber, city, street, osm_type)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            "INSERT INTO pois (id, name, category, subcategory, latitude, longitude, housenumber, city, street, osm_type,
+                centroid_latitude, centroid_longitude, interior_latitude, interior_longitude,
+                min_lat, min_lon, max_lat, max_lon, geometry, municipality, county, province,
+                address_source, address_distance_m, opening_hours, opening_hours_normalized, phone,
+                website, brand, brand_wikidata, operator, tags)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19,
+                ?20, ?21, ?22, ?23, ?24, ?25, ?26, ?27, ?28, ?29, ?30, ?31, ?32)",
+        )?;
+
+        let mut category_stmt = tx.prepare(
+            "INSERT INTO poi_categories (osm_type, id, rank, category, subcategory, tag_key)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )?;
+
+        let mut name_stmt = tx.prepare(
+            "INSERT INTO poi_names (osm_type, id, kind, language, name)
+            VALUES (?1, ?2, ?3, ?4, ?5)",
         )?;
 
         for poi in pois {
@@ -423,24 +907,69 @@ fn export_to_sqlite(
                 poi.city,
                 poi.street,
                 poi.osm_type,
+                poi.centroid_latitude,
+                poi.centroid_longitude,
+                poi.interior_latitude,
+                poi.interior_longitude,
+                poi.min_lat,
+                poi.min_lon,
+                poi.max_lat,
+                poi.max_lon,
+                poi.geometry,
+                poi.municipality,
+                poi.county,
+                poi.province,
+                poi.address_source,
+                poi.address_distance_m,
+                poi.details.opening_hours,
+                poi.details.opening_hours_normalized,
+                poi.details.phone,
+                poi.details.website,
+                poi.details.brand,
+                poi.details.brand_wikidata,
+                poi.details.operator,
+                tags_json(&poi.tags)?,
             ])?;
+
+            for (rank, m) in poi.categories.iter().enumerate() {
+                category_stmt.execute(params![
+                    poi.osm_type,
+                    poi.id,
+                    rank as i64,
+                    m.category,
+                    m.subcategory,
+                    m.tag_key,
+                ])?;
+            }
+
+            for variant in &poi.names {
+                name_stmt.execute(params![
+                    poi.osm_type,
+                    poi.id,
+                    variant.kind,
+                    variant.language,
+                    variant.name,
+                ])?;
+            }
         }
     }
 
     tx.commit()?;
-    println!("  ✓ POIs inserted");
-    println!("  Inserting {} addresses...", addresses.len());
+    progress!("  ✓ POIs inserted");
+    progress!("  Inserting {} addresses...", addresses.len());
     let tx = conn.unchecked_transaction()?;
 
     {
         let mut stmt = tx.prepare(
-            "INSERT INTO addresses (id, housenumber, street, city, postcode, suburb, place, latitude, longitude, full_address)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            "INSERT INTO addresses (id, osm_type, housenumber, street, city, postcode, suburb, place, latitude, longitude, full_address,
+                interpolated, source_way_id, municipality, county, province, tags)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17)",
         )?;
 
         for addr in addresses {
             stmt.execute(params![
                 addr.id,
+                addr.osm_type,
                 addr.housenumber,
                 addr.street,
                 addr.city,
@@ -450,81 +979,339 @@ fn export_to_sqlite(
                 addr.latitude,
                 addr.longitude,
                 addr.full_address,
+                addr.interpolated,
+                addr.source_way_id,
+                addr.municipality,
+                addr.county,
+                addr.province,
+                tags_json(&addr.tags)?,
             ])?;
         }
     }
 
     tx.commit()?;
-    println!("  ✓ Addresses inserted");
+    progress!("  ✓ Addresses inserted");
+    progress!("  Inserting {} places...", places.len());
+    let tx = conn.unchecked_transaction()?;
+
+    {
+        let mut stmt = tx.prepare(
+            "INSERT INTO places (id, name, place, population, latitude, longitude)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )?;
+
+        for place in places {
+            stmt.execute(params![
+                place.id,
+                place.name,
+                place.place,
+                place.population,
+                place.latitude,
+                place.longitude,
+            ])?;
+        }
+    }
+
+    tx.commit()?;
+    progress!("  ✓ Places inserted");
+
+    create_search_index(&conn)?;
+    create_spatial_index(&conn)?;
 
     // optimizing the database
     conn.execute("ANALYZE", [])?;
     conn.execute("VACUUM", [])?;
+    Ok(())
+}
+
+// full-text indexes for autocomplete, keyed by poi_id and address_id
+// the tables are contentless, matches are joined back to pois and addresses for the columns
+fn create_search_index(conn: &Connection) -> SqlResult<()> {
+    progress!("  Building full-text search index...");
+
+    // remove_diacritics 2 folds é to e, prefix indexes make "tim hort*" queries fast
+    conn.execute_batch(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS pois_fts USING fts5(
+            name, names, full_address,
+            content = '', tokenize = 'unicode61 remove_diacritics 2', prefix = '2 3 4'
+        );
+        CREATE VIRTUAL TABLE IF NOT EXISTS addresses_fts USING fts5(
+            full_address,
+            content = '', tokenize = 'unicode61 remove_diacritics 2', prefix = '2 3 4'
+        );
+        INSERT INTO pois_fts (rowid, name, names, full_address)
+            SELECT p.poi_id, p.name,
+                (SELECT group_concat(n.name, ' ') FROM poi_names n
+                    WHERE n.osm_type = p.osm_type AND n.id = p.id AND n.name != p.name),
+                p.full_address
+            FROM pois p;
+        INSERT INTO addresses_fts (rowid, full_address)
+            SELECT address_id, full_address FROM addresses;
+        INSERT INTO pois_fts (pois_fts) VALUES ('optimize');
+        INSERT INTO addresses_fts (addresses_fts) VALUES ('optimize');",
+    )?;
 
-    println!("✓ SQLite database created successfully");
+    progress!("  ✓ Search index built");
     Ok(())
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        eprintln!("Usage: {} <pbf_file>", args[0]);
-        eprintln!("\nExample: {} ontario-latest.osm.pbf", args[0]);
-        std::process::exit(1);
+// r*tree indexes for bounding box and nearby queries, keyed by poi_id and address_id
+// way and relation pois are indexed by their extent, everything else by its point
+fn create_spatial_index(conn: &Connection) -> SqlResult<()> {
+    progress!("  Building spatial index...");
+
+    conn.execute_batch(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS pois_rtree USING rtree(
+            id, min_lon, max_lon, min_lat, max_lat
+        );
+        CREATE VIRTUAL TABLE IF NOT EXISTS addresses_rtree USING rtree(
+            id, min_lon, max_lon, min_lat, max_lat
+        );
+        INSERT INTO pois_rtree (id, min_lon, max_lon, min_lat, max_lat)
+            SELECT poi_id, coalesce(min_lon, longitude), coalesce(max_lon, longitude),
+                coalesce(min_lat, latitude), coalesce(max_lat, latitude)
+            FROM pois;
+        INSERT INTO addresses_rtree (id, min_lon, max_lon, min_lat, max_lat)
+            SELECT address_id, longitude, longitude, latitude, latitude FROM addresses;",
+    )?;
+
+    progress!("  ✓ Spatial index built");
+    Ok(())
+}
+
+// wkb goes in as a blob, encoded polylines as text
+impl ToSql for Geometry {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(match self {
+            Geometry::Wkb(bytes) => ToSqlOutput::Borrowed(ValueRef::Blob(bytes)),
+            Geometry::Polyline(text) => ToSqlOutput::Borrowed(ValueRef::Text(text.as_bytes())),
+        })
     }
+}
 
-    let pbf_path = &args[1];
-    println!("{}", "=".repeat(80));
-    println!("OSM PBF Fast Extractor (Rust) - Two-Pass Version");
-    println!("{}", "=".repeat(80));
-    println!("Input file: {}", pbf_path);
-    println!();
+// kept tags go into sqlite as a json object text, queryable with json_extract
+fn tags_json(tags: &Option<BTreeMap<String, String>>) -> SqlResult<Option<String>> {
+    tags.as_ref()
+        .map(serde_json::to_string)
+        .transpose()
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+}
 
-    let start = Instant::now();
-    let category_map = get_category_mapping();
+#[derive(Serialize)]
+struct JsonExport<'a> {
+    pois: &'a [PointOfInterest],
+    addresses: &'a [Address],
+    places: &'a [Place],
+}
 
-    // pass 1: storing all the node coordinates
-    println!("PASS 1: Reading node coordinates...");
-    let pass1_start = Instant::now();
-    let mut node_coords: HashMap<i64, (f64, f64)> = HashMap::new();
+fn export_to_json(
+    pois: &[PointOfInterest],
+    addresses: &[Address],
+    places: &[Place],
+    json_path: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    progress!("Writing JSON to {}...", json_path.display());
+
+    let writer = BufWriter::new(File::create(json_path)?);
+    serde_json::to_writer_pretty(
+        writer,
+        &JsonExport {
+            pois,
+            addresses,
+            places,
+        },
+    )?;
 
-    let reader = ElementReader::from_path(pbf_path)?;
-    let mut count = 0;
+    progress!("✓ JSON written successfully");
+    Ok(())
+}
 
-    reader.for_each(|element| {
-        match element {
-            Element::Node(node) => {
-                node_coords.insert(node.id(), (node.lat(), node.lon()));
-            }
-            Element::DenseNode(node) => {
-                node_coords.insert(node.id(), (node.lat(), node.lon()));
-            }
-            _ => {}
+// a poi or address as a geojson feature, its fields become the properties
+#[derive(Serialize)]
+struct Feature {
+    #[serde(rename = "type")]
+    feature_type: &'static str,
+    geometry: FeatureGeometry,
+    properties: serde_json::Map<String, serde_json::Value>,
+}
+
+// geojson positions are [lon, lat]
+#[derive(Serialize)]
+#[serde(tag = "type", content = "coordinates")]
+enum FeatureGeometry {
+    Point([f64; 2]),
+    Polygon(Vec<Vec<[f64; 2]>>),
+    MultiPolygon(Vec<Vec<Vec<[f64; 2]>>>),
+}
+
+impl FeatureGeometry {
+    // a poi with an outline is written as its area, everything else as its point
+    fn of_poi(poi: &PointOfInterest) -> FeatureGeometry {
+        let rings = |polygon: &Polygon| {
+            std::iter::once(&polygon.outer)
+                .chain(&polygon.holes)
+                .map(|ring| ring.iter().map(|&(lat, lon)| [lon, lat]).collect())
+                .collect()
+        };
+        match poi.outline.as_slice() {
+            [] => FeatureGeometry::Point([poi.longitude, poi.latitude]),
+            [polygon] => FeatureGeometry::Polygon(rings(polygon)),
+            polygons => FeatureGeometry::MultiPolygon(polygons.iter().map(rings).collect()),
+        }
+    }
+}
+
+// the encoded --geometry outline stays out of the properties, the feature geometry is
+// the outline itself
+fn feature<T: Serialize>(
+    kind: &'static str,
+    item: &T,
+    geometry: FeatureGeometry,
+) -> Result<Feature, serde_json::Error> {
+    let mut properties = serde_json::Map::new();
+    // "poi" or "address", both end up in the same layer
+    properties.insert("kind".to_string(), kind.into());
+    if let serde_json::Value::Object(fields) = serde_json::to_value(item)? {
+        properties.extend(fields.into_iter().filter(|(key, _)| key != "geometry"));
+    }
+    Ok(Feature {
+        feature_type: "Feature",
+        geometry,
+        properties,
+    })
+}
+
+// features are written one at a time so nothing the size of the output is built in memory
+// `seq` writes one feature per line instead of a FeatureCollection
+fn export_to_geojson(
+    pois: &[PointOfInterest],
+    addresses: &[Address],
+    path: &Path,
+    seq: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    progress!("Writing GeoJSON to {}...", path.display());
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    if !seq {
+        writer.write_all(b"{\"type\":\"FeatureCollection\",\"features\":[\n")?;
+    }
+
+    let mut first = true;
+    let poi_features = pois
+        .iter()
+        .map(|p| feature("poi", p, FeatureGeometry::of_poi(p)));
+    write_features(&mut writer, poi_features, seq, &mut first)?;
+    let address_features = addresses.iter().map(|a| {
+        let point = FeatureGeometry::Point([a.longitude, a.latitude]);
+        feature("address", a, point)
+    });
+    write_features(&mut writer, address_features, seq, &mut first)?;
+
+    if !seq {
+        writer.write_all(b"\n]}\n")?;
+    }
+    writer.flush()?;
+
+    progress!("✓ GeoJSON written successfully");
+    Ok(())
+}
+
+// `first` tracks whether a comma is needed across calls writing into one FeatureCollection
+fn write_features(
+    writer: &mut impl Write,
+    features: impl Iterator<Item = Result<Feature, serde_json::Error>>,
+    seq: bool,
+    first: &mut bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for feature in features {
+        if !seq && !*first {
+            writer.write_all(b",\n")?;
         }
-        count += 1;
-        if count % 10_000_000 == 0 {
-            println!("  Stored {}M node coordinates...", count / 1_000_000);
+        *first = false;
+        serde_json::to_writer(&mut *writer, &feature?)?;
+        if seq {
+            writer.write_all(b"\n")?;
         }
-    })?;
+    }
+    Ok(())
+}
 
-    println!(
-        "✓ Pass 1 complete in {:.2?} - Stored {} node coordinates",
-        pass1_start.elapsed(),
-        node_coords.len()
-    );
-    println!();
+// an extracted multipolygon waiting for its member way geometries
+struct PendingRelation {
+    id: i64,
+    tags: HashMap<String, String>,
+    categories: Vec<CategoryMatch>,
+    outer_way_ids: Vec<i64>,
+    inner_way_ids: Vec<i64>,
+    // set for boundary=administrative relations
+    admin_level: Option<u8>,
+}
 
-    // pass 2: extracting pois and addresses
-    println!("PASS 2: Extracting POIs and addresses...");
-    let pass2_start = Instant::now();
-    let mut pois: Vec<PointOfInterest> = Vec::new();
-    let mut addresses: Vec<Address> = Vec::new();
-    let mut address_index: RTree<AddressPoint> = RTree::new();
+// what pass 2 finds in one block, merged across worker threads
+#[derive(Default)]
+struct Pass2Output {
+    pois: Vec<PointOfInterest>,
+    addresses: Vec<Address>,
+    relation_way_coords: HashMap<i64, Vec<Coord>>,
+    relations: Vec<PendingRelation>,
+    interpolations: Vec<PendingInterpolation>,
+    admin_areas: Vec<AdminArea>,
+    places: Vec<Place>,
+    road_segments: Vec<RoadSegment>,
+}
 
-    let reader = ElementReader::from_path(pbf_path)?;
-    let mut processed = 0;
+impl Pass2Output {
+    fn merge(mut self, other: Pass2Output) -> Pass2Output {
+        self.pois.extend(other.pois);
+        self.addresses.extend(other.addresses);
+        self.relation_way_coords.extend(other.relation_way_coords);
+        self.relations.extend(other.relations);
+        self.interpolations.extend(other.interpolations);
+        self.admin_areas.extend(other.admin_areas);
+        self.places.extend(other.places);
+        self.road_segments.extend(other.road_segments);
+        self
+    }
+}
 
-    reader.for_each(|element| {
+// read-only state shared by the pass 2 workers
+struct Pass2Context<'a> {
+    category_map: &'a CategoryMapping,
+    node_coords: &'a dyn NodeStore,
+    relation_way_ids: &'a HashSet<i64>,
+    bbox: Option<BoundingBox>,
+    geometry: Option<GeometryFormat>,
+    tag_filter: Option<TagFilter>,
+    // roads are only collected for enrichment
+    enrich: bool,
+    // area pois keep their polygons for the geojson formats
+    keep_outlines: bool,
+}
+
+impl Pass2Context<'_> {
+    // an element is kept when its point falls inside the requested bbox, if any
+    fn in_bbox(&self, lat: f64, lon: f64) -> bool {
+        self.bbox.is_none_or(|b| b.contains(lat, lon))
+    }
+
+    fn kept_tags(&self, tags: &HashMap<String, String>) -> Option<BTreeMap<String, String>> {
+        self.tag_filter.as_ref().map(|f| f.apply(tags))
+    }
+}
+
+// output order: nodes, then ways, then relations, the order they appear in the file
+fn osm_type_rank(osm_type: &str) -> u8 {
+    match osm_type {
+        "node" => 0,
+        "way" => 1,
+        _ => 2,
+    }
+}
+
+fn extract_block(block: &PrimitiveBlock, ctx: &Pass2Context) -> Pass2Output {
+    let mut out = Pass2Output::default();
+
+    for element in block.elements() {
         match &element {
             Element::Node(node) => {
                 let node_id = node.id();
@@ -535,16 +1322,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     .map(|(k, v)| (k.to_string(), v.to_string()))
                     .collect();
 
-                process_node_tags(
-                    node_id,
-                    lat,
-                    lon,
-                    tags,
-                    &category_map,
-                    &mut pois,
-                    &mut addresses,
-                    &mut address_index,
-                );
+                if ctx.in_bbox(lat, lon) {
+                    process_node_tags(node_id, lat, lon, tags, ctx, &mut out);
+                }
             }
             Element::DenseNode(node) => {
                 let node_id = node.id();
@@ -555,16 +1335,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     .map(|(k, v)| (k.to_string(), v.to_string()))
                     .collect();
 
-                process_node_tags(
-                    node_id,
-                    lat,
-                    lon,
-                    tags,
-                    &category_map,
-                    &mut pois,
-                    &mut addresses,
-                    &mut address_index,
-                );
+                if ctx.in_bbox(lat, lon) {
+                    process_node_tags(node_id, lat, lon, tags, ctx, &mut out);
+                }
             }
             Element::Way(way) => {
                 let tags: HashMap<String, String> = way
@@ -572,130 +1345,881 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     .map(|(k, v)| (k.to_string(), v.to_string()))
                     .collect();
 
+                if ctx.relation_way_ids.contains(&way.id()) {
+                    let coords: Vec<Coord> = way
+                        .refs()
+                        .filter_map(|node_id| ctx.node_coords.get(node_id))
+                        .collect();
+                    out.relation_way_coords.insert(way.id(), coords);
+                }
+
+                // interpolation lines are filled in once all address nodes are known
+                if let Some(step) = interpolation::interpolation_step(&tags) {
+                    out.interpolations.push(PendingInterpolation {
+                        way_id: way.id(),
+                        step,
+                        street: tags.get("addr:street").cloned().unwrap_or_default(),
+                        nodes: way
+                            .refs()
+                            .filter_map(|node_id| Some((node_id, ctx.node_coords.get(node_id)?)))
+                            .collect(),
+                    });
+                    continue;
+                }
+
+                // named streets feed the nearest-road lookup during enrichment
+                if let Some(name) = roads::road_name(&tags).filter(|_| ctx.enrich) {
+                    let coords: Vec<Coord> = way
+                        .refs()
+                        .filter_map(|node_id| ctx.node_coords.get(node_id))
+                        .collect();
+                    out.road_segments
+                        .extend(roads::road_segments(way.id(), name, &coords));
+                }
+
                 // checking for poi category
-                let mut category: Option<String> = None;
-                let mut subcategory: Option<String> = None;
-
-                for (tag_key, value_map) in category_map.iter() {
-                    if let Some(tag_value) = tags.get(tag_key) {
-                        if let Some(cat) = value_map.get(tag_value) {
-                            category = Some(cat.clone());
-                            subcategory = Some(tag_value.clone());
-                            break;
-                        }
+                let classified = ctx.category_map.classify(&tags);
+
+                // extracting ways that have categories like georgian college, and
+                // building outlines carrying an address
+                if !classified.is_empty() || has_address_tags(&tags) {
+                    let coords: Vec<Coord> = way
+                        .refs()
+                        .filter_map(|node_id| ctx.node_coords.get(node_id))
+                        .collect();
+                    // closed ways are areas, they get proper area points too
+                    let polygons: Vec<Polygon> =
+                        geometry::closed_way_polygon(&coords).into_iter().collect();
+                    let area = geometry::area_points(&polygons);
+
+                    if let Some(point) = geometry::mean_point(&coords) {
+                        push_area_element(
+                            &mut out,
+                            AreaElement {
+                                id: way.id(),
+                                osm_type: "way",
+                                tags: &tags,
+                                categories: classified,
+                                point,
+                                coords: &coords,
+                                polygons: &polygons,
+                                area,
+                            },
+                            ctx,
+                        );
                     }
                 }
+            }
+            Element::Relation(relation) => {
+                let tags: HashMap<String, String> = relation
+                    .tags()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect();
 
-                // extracting ways that have names and categories like georgian college
-                if category.is_some() || tags.contains_key("name") {
-                    let node_refs: Vec<i64> = way.refs().collect();
-                    if !node_refs.is_empty() {
-                        let mut lat_sum = 0.0;
-                        let mut lon_sum = 0.0;
-                        let mut valid_nodes = 0;
-
-                        for node_id in &node_refs {
-                            if let Some((lat, lon)) = node_coords.get(node_id) {
-                                lat_sum += lat;
-                                lon_sum += lon;
-                                valid_nodes += 1;
-                            }
+                let admin_level = admin::admin_level(&tags);
+                if admin_level.is_some() || is_extracted_multipolygon(&tags, ctx.category_map) {
+                    let mut outer_way_ids = Vec::new();
+                    let mut inner_way_ids = Vec::new();
+                    for member in relation.members() {
+                        let role = member.role().unwrap_or("");
+                        if member.member_type != RelMemberType::Way {
+                            continue;
                         }
-
-                        if valid_nodes > 0 {
-                            let centroid_lat = lat_sum / valid_nodes as f64;
-                            let centroid_lon = lon_sum / valid_nodes as f64;
-
-                            if let Some(cat) = category {
-                                let mut housenumber =
-                                    tags.get("addr:housenumber").cloned().unwrap_or_default();
-                                let mut street =
-                                    tags.get("addr:street").cloned().unwrap_or_default();
-                                let mut city = tags.get("addr:city").cloned().unwrap_or_default();
-
-                                // If no address info, find nearest address
-                                if street.is_empty() && housenumber.is_empty() {
-                                    if let Some((nearest_num, nearest_street, nearest_city)) =
-                                        find_nearest_address(
-                                            &address_index,
-                                            centroid_lat,
-                                            centroid_lon,
-                                        )
-                                    {
-                                        housenumber = nearest_num;
-                                        street = nearest_street;
-                                        if city.is_empty() {
-                                            city = nearest_city;
-                                        }
-                                    }
-                                }
-
-                                pois.push(PointOfInterest {
-                                    id: way.id(),
-                                    name: tags
-                                        .get("name")
-                                        .cloned()
-                                        .unwrap_or_else(|| "Unnamed".to_string()),
-                                    category: cat,
-                                    subcategory: subcategory.unwrap_or_default(),
-                                    latitude: centroid_lat,
-                                    longitude: centroid_lon,
-                                    housenumber,
-                                    city,
-                                    street,
-                                    osm_type: "way".to_string(),
-                                });
-                            }
+                        if is_outer_role(role) {
+                            outer_way_ids.push(member.member_id);
+                        } else if is_inner_role(role) {
+                            inner_way_ids.push(member.member_id);
                         }
                     }
+
+                    out.relations.push(PendingRelation {
+                        id: relation.id(),
+                        categories: ctx.category_map.classify(&tags),
+                        tags,
+                        outer_way_ids,
+                        inner_way_ids,
+                        admin_level,
+                    });
                 }
             }
-            Element::Relation(_) => {
-                //TODO not doing relations for now, thats a whole other can of worms for later
+        }
+    }
+
+    out
+}
+
+// turns pending multipolygons into pois and addresses now that all member way geometries are known
+fn resolve_relations(out: &mut Pass2Output, ctx: &Pass2Context) {
+    for relation in std::mem::take(&mut out.relations) {
+        let member_coords = |ids: &[i64]| -> Vec<Vec<Coord>> {
+            ids.iter()
+                .filter_map(|id| out.relation_way_coords.get(id).cloned())
+                .collect()
+        };
+        let outer_ways = member_coords(&relation.outer_way_ids);
+        let all_coords: Vec<Coord> = outer_ways.iter().flatten().copied().collect();
+        let (point, polygons) =
+            assemble_multipolygon(outer_ways, member_coords(&relation.inner_way_ids));
+
+        // boundaries are only kept for the point-in-polygon lookups
+        if is_extracted_multipolygon(&relation.tags, ctx.category_map) {
+            if let Some(point) = point {
+                let area = geometry::area_points(&polygons);
+                push_area_element(
+                    out,
+                    AreaElement {
+                        id: relation.id,
+                        osm_type: "relation",
+                        tags: &relation.tags,
+                        categories: relation.categories,
+                        point,
+                        coords: &all_coords,
+                        polygons: &polygons,
+                        area,
+                    },
+                    ctx,
+                );
             }
         }
 
-        processed += 1;
-        if processed % 10_000_000 == 0 {
-            println!(
-                "  Processed {}M elements - Found {} POIs, {} addresses",
-                processed / 1_000_000,
-                pois.len(),
-                addresses.len()
-            );
+        if let Some(admin_level) = relation.admin_level {
+            if !polygons.is_empty() {
+                out.admin_areas.push(AdminArea {
+                    id: relation.id,
+                    name: relation.tags.get("name").cloned().unwrap_or_default(),
+                    admin_level,
+                    polygons,
+                });
+            }
         }
-    })?;
+    }
+}
 
-    println!("✓ Pass 2 complete in {:.2?}", pass2_start.elapsed());
-    println!();
+// a way or relation with its geometry resolved, `coords` are all of its nodes and
+// `polygons` its area (empty for open ways)
+struct AreaElement<'a> {
+    id: i64,
+    osm_type: &'a str,
+    tags: &'a HashMap<String, String>,
+    categories: Vec<CategoryMatch>,
+    point: Coord,
+    coords: &'a [Coord],
+    polygons: &'a [Polygon],
+    area: Option<AreaPoints>,
+}
 
-    enrich_pois_with_addresses(&mut pois, &address_index);
-    println!();
+// adds the poi and the address row a way or relation stands for, if any
+fn push_area_element(out: &mut Pass2Output, element: AreaElement, ctx: &Pass2Context) {
+    // addressed buildings are placed inside their outline, not at the poi point
+    let address_point = element.area.map_or(element.point, |a| a.interior);
+    if ctx.in_bbox(address_point.0, address_point.1) {
+        out.addresses.extend(address_from_tags(
+            element.id,
+            element.osm_type,
+            address_point,
+            element.tags,
+            ctx.tag_filter.as_ref(),
+        ));
+    }
+
+    if !element.categories.is_empty() && ctx.in_bbox(element.point.0, element.point.1) {
+        out.pois.push(area_poi(element, ctx));
+    }
+}
 
-    println!("Final Results:");
-    println!(
-        "  POIs found: {} ({} from nodes, {} from ways)",
+// synthesizes the addresses along interpolation ways from the address nodes they connect
+fn resolve_interpolations(out: &mut Pass2Output, ctx: &Pass2Context) {
+    let interpolations = std::mem::take(&mut out.interpolations);
+    if interpolations.is_empty() {
+        return;
+    }
+
+    let node_addresses: HashMap<i64, &Address> = out
+        .addresses
+        .iter()
+        .filter(|a| a.osm_type == "node")
+        .map(|a| (a.id, a))
+        .collect();
+    let mut interpolated: Vec<Address> = interpolations
+        .iter()
+        .flat_map(|line| interpolation::interpolate(line, &node_addresses))
+        .collect();
+    interpolated.retain(|a| ctx.in_bbox(a.latitude, a.longitude));
+
+    detail!(
+        "  {} interpolation ways, {} interpolated addresses",
+        interpolations.len(),
+        interpolated.len()
+    );
+    out.addresses.extend(interpolated);
+}
+
+// builds the poi for a way or relation that matched a category
+fn area_poi(element: AreaElement, ctx: &Pass2Context) -> PointOfInterest {
+    let AreaElement {
+        id,
+        osm_type,
+        tags,
+        categories,
+        point: (lat, lon),
+        coords,
+        polygons,
+        area,
+    } = element;
+    let primary = &categories[0];
+    let bounds = geometry::bounds(coords);
+    // missing addresses are filled in by enrichment afterwards
+    let (housenumber, street, city) = tagged_address(tags);
+
+    PointOfInterest {
+        id,
+        name: names::primary_name(tags).unwrap_or_else(|| "Unnamed".to_string()),
+        category: primary.category.clone(),
+        subcategory: primary.subcategory.clone(),
+        latitude: lat,
+        longitude: lon,
+        address_source: tagged_source(&housenumber, &street, &city),
+        address_distance_m: None,
+        housenumber,
+        city,
+        street,
+        osm_type: osm_type.to_string(),
+        centroid_latitude: area.map(|a| a.centroid.0),
+        centroid_longitude: area.map(|a| a.centroid.1),
+        interior_latitude: area.map(|a| a.interior.0),
+        interior_longitude: area.map(|a| a.interior.1),
+        min_lat: bounds.map(|(min, _)| min.0),
+        min_lon: bounds.map(|(min, _)| min.1),
+        max_lat: bounds.map(|(_, max)| max.0),
+        max_lon: bounds.map(|(_, max)| max.1),
+        geometry: ctx
+            .geometry
+            .and_then(|format| geometry::encode_geometry(polygons, format)),
+        outline: if ctx.keep_outlines {
+            polygons.to_vec()
+        } else {
+            Vec::new()
+        },
+        municipality: String::new(),
+        county: String::new(),
+        province: String::new(),
+        details: PoiDetails::from_tags(tags),
+        names: names::name_variants(tags),
+        tags: ctx.kept_tags(tags),
+        categories,
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+    cli::set_verbosity(&cli);
+
+    match &cli.command {
+        Command::Extract(args) => run_extract(args),
+        Command::Inspect(args) => run_inspect(args),
+        Command::Query(args) => run_query(args),
+        Command::OpenAt(args) => run_open_at(args),
+        Command::Reverse(args) => run_reverse(args),
+        Command::Search(args) => run_search(args),
+        Command::Serve(args) => run_serve(args),
+        Command::Validate(args) => run_validate(args),
+    }
+}
+
+fn run_extract(args: &ExtractArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let pbf_path = &args.pbf_file;
+    let output_path = args.output_path();
+    let bbox = args.bbox;
+
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(args.threads)
+        .build_global()?;
+
+    progress!("{}", "=".repeat(80));
+    progress!("OSM PBF Fast Extractor (Rust) - Two-Pass Version");
+    progress!("{}", "=".repeat(80));
+    progress!("Input file: {}", pbf_path.display());
+    progress!("Output file: {}", output_path.display());
+    progress!("Threads: {}", rayon::current_num_threads());
+    if let Some(b) = bbox {
+        progress!(
+            "Bounding box: {},{},{},{}",
+            b.min_lon,
+            b.min_lat,
+            b.max_lon,
+            b.max_lat
+        );
+    }
+    progress!();
+
+    let tag_filter = if args.keep_tags {
+        Some(
+            TagFilter::new(args.tag_include.clone(), args.tag_exclude.clone())
+                .map_err(|e| format!("Invalid tag filter: {}", e))?,
+        )
+    } else {
+        None
+    };
+    if args.max_enrich_distance.is_nan() || args.max_enrich_distance < 0.0 {
+        return Err(format!(
+            "Invalid --max-enrich-distance: {}, expected a distance in metres",
+            args.max_enrich_distance
+        )
+        .into());
+    }
+
+    let start = Instant::now();
+    let mut category_map = match &args.categories {
+        Some(path) => CategoryMapping::from_file(path)?,
+        None => CategoryMapping::builtin(),
+    };
+    if let Some(keys) = &args.precedence {
+        category_map
+            .set_key_precedence(keys.clone())
+            .map_err(|e| format!("Invalid --precedence: {}", e))?;
+    }
+    progress!(
+        "Category rules: {} ({})",
+        category_map.rules().len(),
+        args.categories
+            .as_ref()
+            .map_or("built-in".to_string(), |p| p.display().to_string())
+    );
+    detail!(
+        "Key precedence: {}",
+        category_map.key_precedence().join(" > ")
+    );
+    progress!();
+
+    // pre-pass: which nodes are worth keeping at all
+    let referenced_nodes = if args.only_referenced_nodes {
+        progress!("PASS 0: Collecting nodes referenced by POI ways and relations...");
+        let pass0_start = Instant::now();
+        let referenced = collect_referenced_nodes(pbf_path, &category_map, !args.no_enrich)?;
+        progress!(
+            "✓ Pass 0 complete in {:.2?} - {} referenced nodes",
+            pass0_start.elapsed(),
+            referenced.len()
+        );
+        progress!();
+        Some(referenced)
+    } else {
+        None
+    };
+    // nodes carry their own coordinates in pass 2, pass 1 is only for way lookups
+    let keep_node = |id: i64| {
+        referenced_nodes
+            .as_ref()
+            .is_none_or(|r| r.binary_search(&id).is_ok())
+    };
+
+    // pass 1: storing the node coordinates
+    progress!("PASS 1: Reading node coordinates...");
+    let pass1_start = Instant::now();
+    let node_coords = node_store::create(args.node_store, args.node_store_file.as_deref())
+        .map_err(|e| format!("Could not create the node store: {}", e))?;
+    detail!("  Using {:?} node store", args.node_store);
+
+    // workers decode blocks in parallel and take turns writing their nodes into the store
+    let node_coords = Mutex::new(node_coords);
+    // outer ways of multipolygon pois, so pass 2 knows which way geometries to keep
+    // a failed insert comes back as the error of its block and ends the pass
+    let relation_way_ids: HashSet<i64> = reader::par_map_blocks(
+        pbf_path,
+        "Read",
+        || Ok(HashSet::new()),
+        |block| -> std::io::Result<HashSet<i64>> {
+            let mut way_ids = HashSet::new();
+            let mut nodes = Vec::new();
+            for element in block.elements() {
+                match element {
+                    Element::Node(node) if keep_node(node.id()) => {
+                        nodes.push((node.id(), node.lat(), node.lon()));
+                    }
+                    Element::DenseNode(node) if keep_node(node.id()) => {
+                        nodes.push((node.id(), node.lat(), node.lon()));
+                    }
+                    Element::Relation(relation) => {
+                        collect_member_way_ids(&relation, &category_map, &mut way_ids);
+                    }
+                    _ => {}
+                }
+            }
+
+            if !nodes.is_empty() {
+                let mut store = node_coords.lock().unwrap();
+                for (id, lat, lon) in nodes {
+                    store.insert(id, lat, lon)?;
+                }
+            }
+            Ok(way_ids)
+        },
+        |a, b| {
+            let (mut a, b) = (a?, b?);
+            a.extend(b);
+            Ok(a)
+        },
+    )?
+    .map_err(|e| format!("Could not store node coordinates: {}", e))?;
+    let mut node_coords = node_coords.into_inner().unwrap();
+    node_coords
+        .finish()
+        .map_err(|e| format!("Could not store node coordinates: {}", e))?;
+
+    progress!(
+        "✓ Pass 1 complete in {:.2?} - Stored {} node coordinates, {} multipolygon member ways",
+        pass1_start.elapsed(),
+        node_coords.len(),
+        relation_way_ids.len()
+    );
+    progress!();
+
+    // pass 2: extracting pois and addresses
+    progress!("PASS 2: Extracting POIs and addresses...");
+    let pass2_start = Instant::now();
+    let ctx = Pass2Context {
+        category_map: &category_map,
+        node_coords: node_coords.as_ref(),
+        relation_way_ids: &relation_way_ids,
+        bbox: args.bbox,
+        geometry: args.geometry,
+        tag_filter,
+        enrich: !args.no_enrich,
+        keep_outlines: matches!(
+            args.format,
+            OutputFormat::Geojson | OutputFormat::Geojsonseq
+        ),
+    };
+
+    let mut extracted = reader::par_map_blocks(
+        pbf_path,
+        "Processed",
+        Pass2Output::default,
+        |block| extract_block(block, &ctx),
+        Pass2Output::merge,
+    )?;
+    // relations can only be assembled once every block's member ways are in
+    resolve_relations(&mut extracted, &ctx);
+    resolve_interpolations(&mut extracted, &ctx);
+
+    // blocks finish in any order, sorting makes the output the same for any thread count
+    let Pass2Output {
+        mut pois,
+        mut addresses,
+        admin_areas,
+        mut places,
+        mut road_segments,
+        ..
+    } = extracted;
+    pois.sort_by_key(|p| (osm_type_rank(&p.osm_type), p.id));
+    addresses.sort_by_key(|a| (osm_type_rank(&a.osm_type), a.id));
+    places.sort_by_key(|p| p.id);
+    road_segments.sort_unstable_by_key(RoadSegment::key);
+
+    progress!(
+        "✓ Pass 2 complete in {:.2?} - Found {} POIs, {} addresses",
+        pass2_start.elapsed(),
+        pois.len(),
+        addresses.len()
+    );
+    progress!();
+
+    if !admin_areas.is_empty() {
+        assign_admin_areas(&mut pois, &mut addresses, admin_areas);
+        progress!();
+    }
+
+    // addresses get their localities first so enrichment can pass them on to pois
+    let place_index = PlaceIndex::new(&places);
+    if !place_index.is_empty() {
+        fill_address_localities(&mut addresses, &place_index);
+    }
+
+    let address_index = build_address_index(&addresses);
+
+    if args.no_enrich {
+        progress!("Skipping POI address enrichment (--no-enrich)");
+    } else {
+        let road_index = RTree::bulk_load(road_segments);
+        enrich_pois_with_addresses(
+            &mut pois,
+            &address_index,
+            &road_index,
+            args.max_enrich_distance,
+        );
+    }
+    if !place_index.is_empty() {
+        fill_poi_localities(&mut pois, &place_index);
+    }
+    progress!();
+
+    progress!("Final Results:");
+    progress!(
+        "  POIs found: {} ({} from nodes, {} from ways, {} from relations)",
         pois.len(),
         pois.iter().filter(|p| p.osm_type == "node").count(),
-        pois.iter().filter(|p| p.osm_type == "way").count()
+        pois.iter().filter(|p| p.osm_type == "way").count(),
+        pois.iter().filter(|p| p.osm_type == "relation").count()
     );
-    println!("  Addresses found: {}", addresses.len());
+    progress!("  Addresses found: {}", addresses.len());
+    progress!("  Places found: {}", places.len());
 
     // count how many POIs got nearest-neighbor addresses
     let pois_with_address = pois
         .iter()
         .filter(|p| !p.street.is_empty() || !p.housenumber.is_empty())
         .count();
-    println!("  POIs with address info: {}", pois_with_address);
-    println!();
+    progress!("  POIs with address info: {}", pois_with_address);
 
-    export_to_sqlite(&pois, &addresses, "osm_data.db")
-        .map_err(|e| format!("SQLite export failed: {}", e))?;
+    let mut category_counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for poi in &pois {
+        *category_counts.entry(poi.category.as_str()).or_default() += 1;
+    }
+    for (category, n) in &category_counts {
+        detail!("    {}: {}", category, n);
+    }
+    progress!();
+
+    match args.format {
+        OutputFormat::Sqlite => export_to_sqlite(&pois, &addresses, &places, &output_path)
+            .map_err(|e| format!("SQLite export failed: {}", e))?,
+        OutputFormat::Json => export_to_json(&pois, &addresses, &places, &output_path)
+            .map_err(|e| format!("JSON export failed: {}", e))?,
+        OutputFormat::Geojson | OutputFormat::Geojsonseq => export_to_geojson(
+            &pois,
+            &addresses,
+            &output_path,
+            args.format == OutputFormat::Geojsonseq,
+        )
+        .map_err(|e| format!("GeoJSON export failed: {}", e))?,
+    }
 
     let total_time = start.elapsed();
-    println!("{}", "=".repeat(80));
-    println!("Complete! Total time: {:.2?}", total_time);
-    println!("{}", "=".repeat(80));
+    progress!("{}", "=".repeat(80));
+    progress!("Complete! Total time: {:.2?}", total_time);
+    progress!("{}", "=".repeat(80));
+
+    Ok(())
+}
+
+fn run_inspect(args: &InspectArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let conn = Connection::open_with_flags(&args.db, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("Could not open {}: {}", args.db.display(), e))?;
+
+    let poi_count: i64 = conn.query_row("SELECT COUNT(*) FROM pois", [], |r| r.get(0))?;
+    let address_count: i64 = conn.query_row("SELECT COUNT(*) FROM addresses", [], |r| r.get(0))?;
+
+    println!("Database: {}", args.db.display());
+    println!("  POIs: {}", poi_count);
+
+    let mut stmt =
+        conn.prepare("SELECT osm_type, COUNT(*) FROM pois GROUP BY osm_type ORDER BY osm_type")?;
+    let rows = stmt.query_map([], |r| Ok((r.get::<_, String>(0)?, r.get::<_, i64>(1)?)))?;
+    for row in rows {
+        let (osm_type, n) = row?;
+        println!("    from {}s: {}", osm_type, n);
+    }
+
+    println!("  POIs by category:");
+    let mut stmt = conn.prepare(
+        "SELECT category, COUNT(*) FROM pois GROUP BY category ORDER BY COUNT(*) DESC, category",
+    )?;
+    let rows = stmt.query_map([], |r| Ok((r.get::<_, String>(0)?, r.get::<_, i64>(1)?)))?;
+    for row in rows {
+        let (category, n) = row?;
+        println!("    {}: {}", category, n);
+    }
+
+    println!("  Addresses: {}", address_count);
+
+    // older databases have no places table
+    let place_count: Option<i64> = conn
+        .query_row("SELECT COUNT(*) FROM places", [], |r| r.get(0))
+        .ok();
+    if let Some(n) = place_count {
+        println!("  Places: {}", n);
+    }
+    Ok(())
+}
+
+fn run_query(args: &QueryArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let conn = Connection::open_with_flags(&args.db, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("Could not open {}: {}", args.db.display(), e))?;
+    let pattern = format!("{}%", args.text);
+    let limit = args.limit as i64;
+
+    println!("POIs:");
+    let mut stmt = conn.prepare(
+        "SELECT osm_type, id, name, category, full_address, latitude, longitude FROM pois
+        WHERE name LIKE ?1 OR full_address LIKE ?1
+            OR (osm_type, id) IN (SELECT osm_type, id FROM poi_names WHERE name LIKE ?1)
+        ORDER BY name LIMIT ?2",
+    )?;
+    let rows = stmt.query_map(params![pattern, limit], |r| {
+        Ok(format!(
+            "  {}/{} {} [{}] {} ({:.6}, {:.6})",
+            r.get::<_, String>(0)?,
+            r.get::<_, i64>(1)?,
+            r.get::<_, String>(2)?,
+            r.get::<_, String>(3)?,
+            r.get::<_, String>(4)?,
+            r.get::<_, f64>(5)?,
+            r.get::<_, f64>(6)?
+        ))
+    })?;
+    for row in rows {
+        println!("{}", row?);
+    }
+
+    println!("Addresses:");
+    let mut stmt = conn.prepare(
+        "SELECT osm_type, id, full_address, latitude, longitude FROM addresses
+        WHERE full_address LIKE ?1
+        ORDER BY full_address LIMIT ?2",
+    )?;
+    let rows = stmt.query_map(params![pattern, limit], |r| {
+        Ok(format!(
+            "  {}/{} {} ({:.6}, {:.6})",
+            r.get::<_, String>(0)?,
+            r.get::<_, i64>(1)?,
+            r.get::<_, String>(2)?,
+            r.get::<_, f64>(3)?,
+            r.get::<_, f64>(4)?
+        ))
+    })?;
+    for row in rows {
+        println!("{}", row?);
+    }
+
+    Ok(())
+}
+
+// "now" or a local date and time
+fn parse_local_time(time: &str) -> Result<chrono::NaiveDateTime, String> {
+    if time.eq_ignore_ascii_case("now") {
+        return Ok(chrono::Local::now().naive_local());
+    }
+    ["%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M"]
+        .iter()
+        .find_map(|format| chrono::NaiveDateTime::parse_from_str(time, format).ok())
+        .ok_or_else(|| {
+            format!(
+                "Invalid time \"{}\", expected now or YYYY-MM-DD HH:MM",
+                time
+            )
+        })
+}
+
+fn run_open_at(args: &OpenAtArgs) -> Result<(), Box<dyn std::error::Error>> {
+    use chrono::{Datelike, Timelike};
+
+    let at = parse_local_time(&args.time)?;
+    let weekday = at.weekday().num_days_from_monday() as usize;
+    let minute = (at.hour() * 60 + at.minute()) as u16;
+
+    let conn = Connection::open_with_flags(&args.db, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("Could not open {}: {}", args.db.display(), e))?;
+    let pattern = format!("{}%", args.name.as_deref().unwrap_or(""));
+
+    // opening hours are evaluated here rather than in sql, only parsed values can be checked
+    let mut stmt = conn.prepare(
+        "SELECT osm_type, id, name, category, full_address, opening_hours_normalized FROM pois
+        WHERE opening_hours_normalized IS NOT NULL AND name LIKE ?1
+            AND (?2 IS NULL OR category = ?2)
+        ORDER BY name",
+    )?;
+    let rows = stmt.query_map(params![pattern, args.category], |r| {
+        Ok((
+            format!(
+                "  {}/{} {} [{}] {}",
+                r.get::<_, String>(0)?,
+                r.get::<_, i64>(1)?,
+                r.get::<_, String>(2)?,
+                r.get::<_, String>(3)?,
+                r.get::<_, String>(4)?,
+            ),
+            r.get::<_, String>(5)?,
+        ))
+    })?;
+
+    println!("Open at {}:", at.format("%a %Y-%m-%d %H:%M"));
+    let mut printed = 0;
+    for row in rows {
+        let (line, hours) = row?;
+        let open =
+            opening_hours::OpeningHours::parse(&hours).is_ok_and(|h| h.is_open(weekday, minute));
+        if open {
+            println!("{} ({})", line, hours);
+            printed += 1;
+            if printed == args.limit {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
 
+fn run_reverse(args: &ReverseArgs) -> Result<(), Box<dyn std::error::Error>> {
+    if !(-90.0..=90.0).contains(&args.lat) || !(-180.0..=180.0).contains(&args.lon) {
+        return Err(format!("Invalid point {},{}", args.lat, args.lon).into());
+    }
+    if !args.max_distance.is_finite() || args.max_distance <= 0.0 {
+        return Err(format!(
+            "Invalid --max-distance {}, it must be a positive number of metres",
+            args.max_distance
+        )
+        .into());
+    }
+    let geocoder = geocode::Geocoder::open(&args.db)?;
+    let result = geocoder.reverse(args.lat, args.lon, args.max_distance)?;
+    println!("{}", serde_json::to_string_pretty(&result)?);
     Ok(())
 }
+
+fn run_search(args: &SearchArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let geocoder = geocode::Geocoder::open(&args.db)?;
+    let hits = geocoder.search(&geocode::SearchQuery {
+        text: &args.text,
+        near: args.near.map(|p| (p.lat, p.lon)),
+        category: args.category.as_deref(),
+        limit: args.limit,
+    })?;
+
+    match args.format {
+        ResultFormat::Json => println!("{}", serde_json::to_string_pretty(&hits)?),
+        ResultFormat::Table => {
+            for hit in &hits {
+                let distance = hit
+                    .distance_m
+                    .map_or(String::new(), |d| format!(" {:.0} m", d));
+                let label = match &hit.category {
+                    Some(category) => format!("{} [{}] {}", hit.name, category, hit.full_address),
+                    None => hit.full_address.clone(),
+                };
+                println!(
+                    "  {:>7.3} {}/{} {}{}",
+                    hit.score, hit.osm_type, hit.id, label, distance
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run_serve(args: &ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let geocoder = geocode::Geocoder::open(&args.db)?;
+    server::serve(&geocoder, &format!("{}:{}", args.host, args.port))
+}
+
+fn run_validate(args: &ValidateArgs) -> Result<(), Box<dyn std::error::Error>> {
+    progress!("Validating {}...", args.pbf_file.display());
+    let start = Instant::now();
+
+    let mut nodes = 0u64;
+    let mut ways = 0u64;
+    let mut relations = 0u64;
+    let mut last_type = 0u8;
+    let mut last_id = i64::MIN;
+    let mut out_of_order = 0u64;
+
+    let reader = ElementReader::from_path(&args.pbf_file)?;
+    reader.for_each(|element| {
+        // extraction doesn't depend on the order, this is only reported; files sorted as
+        // nodes, then ways, then relations by ascending id spare the mmap store a sort
+        let (type_rank, id) = match &element {
+            Element::Node(node) => {
+                nodes += 1;
+                (0, node.id())
+            }
+            Element::DenseNode(node) => {
+                nodes += 1;
+                (0, node.id())
+            }
+            Element::Way(way) => {
+                ways += 1;
+                (1, way.id())
+            }
+            Element::Relation(relation) => {
+                relations += 1;
+                (2, relation.id())
+            }
+        };
+
+        if type_rank < last_type || (type_rank == last_type && id < last_id) {
+            out_of_order += 1;
+        }
+        last_type = type_rank;
+        last_id = id;
+    })?;
+
+    println!("  Nodes: {}", nodes);
+    println!("  Ways: {}", ways);
+    println!("  Relations: {}", relations);
+    progress!("✓ Read in {:.2?}", start.elapsed());
+
+    if out_of_order > 0 {
+        println!(
+            "  {} elements are out of order, which extraction handles; sorting the file \
+             (e.g. osmium sort) makes the mmap node store a little faster",
+            out_of_order
+        );
+    } else {
+        println!("  Elements are sorted by type and id");
+    }
+
+    println!("✓ File is readable and ready for extraction");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address_point(street: &str, lat: f64, lon: f64) -> AddressPoint {
+        AddressPoint {
+            housenumber: "1".to_string(),
+            street: street.to_string(),
+            city: "Barrie".to_string(),
+            point: geometry::to_mercator((lat, lon)),
+        }
+    }
+
+    // at 44.38° a degree of longitude is ~0.71 of a degree of latitude on the ground, so
+    // the east point is nearer although it's farther away in raw degrees
+    #[test]
+    fn nearest_address_is_nearest_on_the_ground() {
+        let (lat, lon) = (44.38, -79.69);
+        let north = address_point("North Street", lat + 0.0009, lon);
+        let east = address_point("East Street", lat, lon + 0.0011);
+        let index = RTree::bulk_load(vec![north, east]);
+
+        let (nearest, distance) = find_nearest_address(&index, lat, lon, 250.0).unwrap();
+        assert_eq!(nearest.street, "East Street");
+        assert!((distance - 87.5).abs() < 1.0, "distance {}", distance);
+    }
+
+    // a poi in the outer wedge of an L corner is closest to the shared node on both roads
+    #[test]
+    fn nearest_road_tie_goes_to_the_lowest_way_id() {
+        let corner = (44.38, -79.69);
+        let alpha = roads::road_segments(2, "Alpha St", &[corner, (44.38, -79.68)]);
+        let beta = roads::road_segments(1, "Beta Ave", &[corner, (44.39, -79.69)]);
+        let (lat, lon) = (44.3795, -79.6907);
+
+        for segments in [
+            [alpha.clone(), beta.clone()].concat(),
+            [beta, alpha].concat(),
+        ] {
+            let index = RTree::bulk_load(segments);
+            let (name, distance) = find_nearest_road(&index, lat, lon, 250.0).unwrap();
+            assert_eq!(name, "Beta Ave");
+            assert!((distance - geometry::haversine_m((lat, lon), corner)).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn nearest_address_respects_max_distance() {
+        let index = RTree::bulk_load(vec![address_point("Far Street", 44.39, -79.69)]);
+        assert!(find_nearest_address(&index, 44.38, -79.69, 250.0).is_none());
+    }
+}
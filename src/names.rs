@@ -0,0 +1,83 @@
+// name variants of a poi: translations, alternative, old and short names
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// name keys we keep, each also with a language suffix like name:fr or alt_name:en
+const NAME_KINDS: [&str; 6] = [
+    "name",
+    "alt_name",
+    "old_name",
+    "short_name",
+    "official_name",
+    "loc_name",
+];
+
+// the languages tried when an element has no plain name tag, Ontario's two official ones
+const FALLBACK_LANGUAGES: [&str; 2] = ["en", "fr"];
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct NameVariant {
+    // one of NAME_KINDS
+    pub kind: String,
+    // None for the untagged local name
+    pub language: Option<String>,
+    pub name: String,
+}
+
+// the name a poi is listed under, `name` or else the English or French one
+pub fn primary_name(tags: &HashMap<String, String>) -> Option<String> {
+    std::iter::once("name".to_string())
+        .chain(
+            FALLBACK_LANGUAGES
+                .iter()
+                .map(|lang| format!("name:{}", lang)),
+        )
+        .filter_map(|key| tags.get(&key))
+        .map(|name| name.trim())
+        .find(|name| !name.is_empty())
+        .map(str::to_string)
+}
+
+// every name variant on the element, sorted by kind, language and name
+// alt_name and friends may hold several names separated by ';'
+pub fn name_variants(tags: &HashMap<String, String>) -> Vec<NameVariant> {
+    let mut variants: Vec<NameVariant> = tags
+        .iter()
+        .filter_map(|(key, value)| {
+            let (kind, language) = match key.split_once(':') {
+                Some((kind, language)) => (kind, Some(language)),
+                None => (key.as_str(), None),
+            };
+            let kind_known = NAME_KINDS.contains(&kind);
+            (kind_known && language.is_none_or(is_language_code)).then_some((kind, language, value))
+        })
+        .flat_map(|(kind, language, value)| {
+            value
+                .split(';')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(move |name| NameVariant {
+                    kind: kind.to_string(),
+                    language: language.map(str::to_string),
+                    name: name.to_string(),
+                })
+        })
+        .collect();
+
+    variants.sort_by(|a, b| {
+        let rank = |v: &NameVariant| NAME_KINDS.iter().position(|k| *k == v.kind);
+        (rank(a), a).cmp(&(rank(b), b))
+    });
+    variants.dedup();
+    variants
+}
+
+// fr, en, zh-Hant, but not name:etymology or name:pronunciation
+fn is_language_code(code: &str) -> bool {
+    let mut parts = code.split('-');
+    let primary = parts.next().unwrap_or("");
+    (2..=3).contains(&primary.len())
+        && primary.chars().all(|c| c.is_ascii_lowercase())
+        && parts
+            .all(|p| !p.is_empty() && p.len() <= 8 && p.chars().all(|c| c.is_ascii_alphanumeric()))
+}
@@ -0,0 +1,304 @@
+// node coordinate stores for pass 1
+// everything except the hash store keeps coordinates as i32 fixed point (1e-7 degrees),
+// the same precision the pbf itself uses
+use crate::geometry::Coord;
+use clap::ValueEnum;
+use memmap2::{Mmap, MmapMut};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+const FIXED_SCALE: f64 = 1e7;
+
+// (lat, lon) in 1e-7 degree units
+type FixedCoord = (i32, i32);
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeStoreKind {
+    /// HashMap keyed by node id, fast but needs the most memory
+    Hash,
+    /// Sorted id/coordinate arrays, best for country and province extracts
+    Sparse,
+    /// Array indexed by node id, best for continent or planet sized files
+    Dense,
+    /// Sorted id/coordinate records in a memory-mapped file on disk
+    Mmap,
+}
+
+pub trait NodeStore: Send + Sync {
+    // only the mmap store can fail, on a full or unwritable disk
+    fn insert(&mut self, id: i64, lat: f64, lon: f64) -> io::Result<()>;
+    fn get(&self, id: i64) -> Option<Coord>;
+    fn len(&self) -> usize;
+
+    // called once after pass 1, before any lookups
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub fn create(kind: NodeStoreKind, path: Option<&Path>) -> io::Result<Box<dyn NodeStore>> {
+    Ok(match kind {
+        NodeStoreKind::Hash => Box::new(HashNodeStore::default()),
+        NodeStoreKind::Sparse => Box::new(SparseNodeStore::default()),
+        NodeStoreKind::Dense => Box::new(DenseNodeStore::default()),
+        NodeStoreKind::Mmap => Box::new(match path {
+            Some(path) => MmapNodeStore::create(path.to_path_buf())?,
+            None => {
+                let path = std::env::temp_dir()
+                    .join(format!("osm-extractor-nodes-{}.bin", std::process::id()));
+                // only ever ours, left behind by a killed run that had the same pid
+                let _ = fs::remove_file(&path);
+                MmapNodeStore::create(path)?
+            }
+        }),
+    })
+}
+
+fn to_fixed(deg: f64) -> i32 {
+    (deg * FIXED_SCALE).round() as i32
+}
+
+fn from_fixed(fixed: i32) -> f64 {
+    fixed as f64 / FIXED_SCALE
+}
+
+#[derive(Default)]
+pub struct HashNodeStore {
+    coords: HashMap<i64, Coord>,
+}
+
+impl NodeStore for HashNodeStore {
+    fn insert(&mut self, id: i64, lat: f64, lon: f64) -> io::Result<()> {
+        self.coords.insert(id, (lat, lon));
+        Ok(())
+    }
+
+    fn get(&self, id: i64) -> Option<Coord> {
+        self.coords.get(&id).copied()
+    }
+
+    fn len(&self) -> usize {
+        self.coords.len()
+    }
+}
+
+// 16 bytes per node, looked up by binary search
+#[derive(Default)]
+pub struct SparseNodeStore {
+    nodes: Vec<(i64, FixedCoord)>,
+}
+
+impl NodeStore for SparseNodeStore {
+    fn insert(&mut self, id: i64, lat: f64, lon: f64) -> io::Result<()> {
+        self.nodes.push((id, (to_fixed(lat), to_fixed(lon))));
+        Ok(())
+    }
+
+    fn get(&self, id: i64) -> Option<Coord> {
+        let idx = self.nodes.binary_search_by_key(&id, |n| n.0).ok()?;
+        let (lat, lon) = self.nodes[idx].1;
+        Some((from_fixed(lat), from_fixed(lon)))
+    }
+
+    fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        // blocks arrive from worker threads in any order
+        self.nodes.sort_unstable_by_key(|n| n.0);
+        Ok(())
+    }
+}
+
+// node ids are split into fixed size blocks that are only allocated once a node lands in
+// them, so memory follows the id range actually used rather than the highest id
+const DENSE_BLOCK_BITS: u32 = 16;
+const DENSE_BLOCK_SIZE: usize = 1 << DENSE_BLOCK_BITS;
+// (0, 0) marks an empty slot, a node exactly on null island is treated as missing
+const DENSE_EMPTY: FixedCoord = (0, 0);
+
+#[derive(Default)]
+pub struct DenseNodeStore {
+    blocks: Vec<Option<Box<[FixedCoord]>>>,
+    // negative ids only show up in unuploaded edits, they don't get a slot in the array
+    negative: HashMap<i64, FixedCoord>,
+    count: usize,
+}
+
+impl NodeStore for DenseNodeStore {
+    fn insert(&mut self, id: i64, lat: f64, lon: f64) -> io::Result<()> {
+        let value = (to_fixed(lat), to_fixed(lon));
+        if id < 0 {
+            if self.negative.insert(id, value).is_none() {
+                self.count += 1;
+            }
+            return Ok(());
+        }
+
+        let block = (id as u64 >> DENSE_BLOCK_BITS) as usize;
+        let offset = id as usize & (DENSE_BLOCK_SIZE - 1);
+        if block >= self.blocks.len() {
+            self.blocks.resize_with(block + 1, || None);
+        }
+
+        let slots = self.blocks[block]
+            .get_or_insert_with(|| vec![DENSE_EMPTY; DENSE_BLOCK_SIZE].into_boxed_slice());
+        if slots[offset] == DENSE_EMPTY {
+            self.count += 1;
+        }
+        slots[offset] = value;
+        Ok(())
+    }
+
+    fn get(&self, id: i64) -> Option<Coord> {
+        let value = if id < 0 {
+            *self.negative.get(&id)?
+        } else {
+            let block = (id as u64 >> DENSE_BLOCK_BITS) as usize;
+            let offset = id as usize & (DENSE_BLOCK_SIZE - 1);
+            self.blocks.get(block)?.as_ref()?[offset]
+        };
+
+        if value == DENSE_EMPTY {
+            return None;
+        }
+        Some((from_fixed(value.0), from_fixed(value.1)))
+    }
+
+    fn len(&self) -> usize {
+        self.count
+    }
+}
+
+// same layout as the sparse store, but the records are written to a file and
+// memory-mapped for lookups, so the os can page them out under memory pressure
+const RECORD_SIZE: usize = 16;
+
+pub struct MmapNodeStore {
+    path: PathBuf,
+    writer: Option<BufWriter<File>>,
+    map: Option<Mmap>,
+    count: usize,
+    last_id: i64,
+    sorted: bool,
+}
+
+impl MmapNodeStore {
+    // the file must not exist yet, it is scratch space and removed again on drop, so an
+    // existing file given by mistake is never truncated or deleted
+    pub fn create(path: PathBuf) -> io::Result<Self> {
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::AlreadyExists => io::Error::new(
+                    e.kind(),
+                    format!(
+                        "{} already exists, remove it or choose another --node-store-file",
+                        path.display()
+                    ),
+                ),
+                _ => e,
+            })?;
+        Ok(MmapNodeStore {
+            path,
+            writer: Some(BufWriter::new(file)),
+            map: None,
+            count: 0,
+            last_id: i64::MIN,
+            sorted: true,
+        })
+    }
+
+    fn record(map: &[u8], idx: usize) -> (i64, i32, i32) {
+        let r = &map[idx * RECORD_SIZE..(idx + 1) * RECORD_SIZE];
+        (
+            i64::from_le_bytes(r[0..8].try_into().unwrap()),
+            i32::from_le_bytes(r[8..12].try_into().unwrap()),
+            i32::from_le_bytes(r[12..16].try_into().unwrap()),
+        )
+    }
+}
+
+impl NodeStore for MmapNodeStore {
+    fn insert(&mut self, id: i64, lat: f64, lon: f64) -> io::Result<()> {
+        let writer = self
+            .writer
+            .as_mut()
+            .expect("node store written to after finish");
+
+        let mut record = [0u8; RECORD_SIZE];
+        record[0..8].copy_from_slice(&id.to_le_bytes());
+        record[8..12].copy_from_slice(&to_fixed(lat).to_le_bytes());
+        record[12..16].copy_from_slice(&to_fixed(lon).to_le_bytes());
+        writer.write_all(&record).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("writing {} failed: {}", self.path.display(), e),
+            )
+        })?;
+
+        if id <= self.last_id {
+            self.sorted = false;
+        }
+        self.last_id = id;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn get(&self, id: i64) -> Option<Coord> {
+        let map = self.map.as_ref()?;
+        let (mut lo, mut hi) = (0, self.count);
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            let (mid_id, lat, lon) = Self::record(map, mid);
+            match mid_id.cmp(&id) {
+                std::cmp::Ordering::Equal => return Some((from_fixed(lat), from_fixed(lon))),
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+            }
+        }
+        None
+    }
+
+    fn len(&self) -> usize {
+        self.count
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+        }
+        if !self.sorted {
+            // sorting in place through a writable mapping keeps this out of the heap
+            let file = fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&self.path)?;
+            // safety: the file is private to this run and nothing else has it mapped
+            let mut map = unsafe { MmapMut::map_mut(&file)? };
+            let records: &mut [[u8; RECORD_SIZE]] =
+                unsafe { std::slice::from_raw_parts_mut(map.as_mut_ptr().cast(), self.count) };
+            records.sort_unstable_by_key(|r| i64::from_le_bytes(r[0..8].try_into().unwrap()));
+            map.flush()?;
+            self.sorted = true;
+        }
+
+        let file = File::open(&self.path)?;
+        // safety: the file is private to this run and no longer written to
+        self.map = Some(unsafe { Mmap::map(&file)? });
+        Ok(())
+    }
+}
+
+impl Drop for MmapNodeStore {
+    fn drop(&mut self) {
+        self.map = None;
+        self.writer = None;
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,269 @@
+// opening_hours parsing for the common weekly subset of the syntax
+// https://wiki.openstreetmap.org/wiki/Key:opening_hours/specification
+//
+// supported: "24/7", rules separated by ';' with weekday ranges and lists (Mo-Fr,Su), time
+// spans (08:00-12:00,13:00-17:00, also past midnight like 22:00-02:00) and off/closed;
+// later rules replace earlier ones for the days they name
+// rules for public holidays (PH) are skipped since there's no holiday calendar to check
+// them against, anything else (months, dates, sunrise, comments, open ends) is rejected
+use std::fmt;
+
+const DAY_NAMES: [&str; 7] = ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"];
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+// open spans per weekday (monday first) in minutes after midnight, an end past 24:00
+// means the span runs into the next day
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpeningHours {
+    week: [Vec<(u16, u16)>; 7],
+}
+
+impl OpeningHours {
+    pub fn parse(src: &str) -> Result<OpeningHours, String> {
+        let src = src.trim();
+        if src.is_empty() {
+            return Err("empty value".to_string());
+        }
+
+        let mut week: [Vec<(u16, u16)>; 7] = Default::default();
+        for rule in src.split(';').map(str::trim).filter(|r| !r.is_empty()) {
+            if rule == "24/7" {
+                week = std::array::from_fn(|_| vec![(0, MINUTES_PER_DAY)]);
+                continue;
+            }
+
+            let (selector, times) = split_rule(rule);
+            let days = match selector {
+                Some(selector) => match parse_days(selector)? {
+                    Some(days) => days,
+                    // public holidays only
+                    None => continue,
+                },
+                None => [true; 7],
+            };
+            let spans = parse_times(times)?;
+
+            for (day, selected) in days.iter().enumerate() {
+                if *selected {
+                    week[day] = spans.clone();
+                }
+            }
+        }
+
+        Ok(OpeningHours { week })
+    }
+
+    // `weekday` 0 is monday, `minute` counts from midnight
+    pub fn is_open(&self, weekday: usize, minute: u16) -> bool {
+        let today = self.week[weekday % 7]
+            .iter()
+            .any(|&(start, end)| start <= minute && minute < end);
+        // spans past midnight from the day before
+        let yesterday = self.week[(weekday + 6) % 7]
+            .iter()
+            .any(|&(_, end)| minute + MINUTES_PER_DAY < end);
+        today || yesterday
+    }
+}
+
+// canonical form, e.g. "Mo-Fr 08:00-17:00; Sa 10:00-14:00; Su off"
+impl fmt::Display for OpeningHours {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.week.iter().all(|d| d == &[(0, MINUTES_PER_DAY)]) {
+            return write!(f, "24/7");
+        }
+
+        let mut rules = Vec::new();
+        let mut start = 0;
+        while start < 7 {
+            let mut end = start;
+            while end + 1 < 7 && self.week[end + 1] == self.week[start] {
+                end += 1;
+            }
+
+            let days = match end - start {
+                0 => DAY_NAMES[start].to_string(),
+                1 => format!("{},{}", DAY_NAMES[start], DAY_NAMES[end]),
+                _ => format!("{}-{}", DAY_NAMES[start], DAY_NAMES[end]),
+            };
+            let spans = if self.week[start].is_empty() {
+                "off".to_string()
+            } else {
+                self.week[start]
+                    .iter()
+                    .map(|&(from, to)| format!("{}-{}", format_time(from), format_time(to)))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            };
+            rules.push(format!("{} {}", days, spans));
+            start = end + 1;
+        }
+        write!(f, "{}", rules.join("; "))
+    }
+}
+
+// "Mo-Fr 08:00-17:00" into the weekday selector and the rest
+fn split_rule(rule: &str) -> (Option<&str>, &str) {
+    let starts_with_day = rule
+        .get(..2)
+        .is_some_and(|p| day_index(p).is_some() || p.eq_ignore_ascii_case("PH"));
+    if !starts_with_day {
+        return (None, rule);
+    }
+    match rule.find(char::is_whitespace) {
+        Some(pos) => (Some(&rule[..pos]), rule[pos..].trim()),
+        None => (Some(rule), ""),
+    }
+}
+
+fn day_index(name: &str) -> Option<usize> {
+    DAY_NAMES.iter().position(|d| d.eq_ignore_ascii_case(name))
+}
+
+// the selected weekdays, None when the selector only names public holidays
+fn parse_days(selector: &str) -> Result<Option<[bool; 7]>, String> {
+    let mut days = [false; 7];
+    let mut any_day = false;
+
+    for part in selector.split(',').map(str::trim) {
+        if part.eq_ignore_ascii_case("PH") {
+            continue;
+        }
+        let (from, to) = part.split_once('-').unwrap_or((part, part));
+        let from = day_index(from).ok_or_else(|| format!("unknown weekday \"{}\"", from))?;
+        let to = day_index(to).ok_or_else(|| format!("unknown weekday \"{}\"", to))?;
+
+        // ranges can wrap around the week, like Sa-Mo
+        let mut day = from;
+        loop {
+            days[day] = true;
+            if day == to {
+                break;
+            }
+            day = (day + 1) % 7;
+        }
+        any_day = true;
+    }
+
+    Ok(any_day.then_some(days))
+}
+
+fn parse_times(times: &str) -> Result<Vec<(u16, u16)>, String> {
+    if times.is_empty() {
+        // a weekday selector on its own means open all day
+        return Ok(vec![(0, MINUTES_PER_DAY)]);
+    }
+    if times.eq_ignore_ascii_case("off") || times.eq_ignore_ascii_case("closed") {
+        return Ok(Vec::new());
+    }
+
+    let mut spans = Vec::new();
+    for span in times.split(',').map(str::trim) {
+        let (from, to) = span
+            .split_once('-')
+            .ok_or_else(|| format!("\"{}\" is not a time span", span))?;
+        let from = parse_time(from)?;
+        let mut to = parse_time(to)?;
+        if to <= from {
+            to += MINUTES_PER_DAY;
+        }
+        if from >= MINUTES_PER_DAY || to > 2 * MINUTES_PER_DAY {
+            return Err(format!("\"{}\" is out of range", span));
+        }
+        spans.push((from, to));
+    }
+    spans.sort_unstable();
+    Ok(spans)
+}
+
+// HH:MM, 24:00 allowed as an end time
+fn parse_time(time: &str) -> Result<u16, String> {
+    let invalid = || format!("\"{}\" is not a time", time);
+    let (hours, minutes) = time.trim().split_once(':').ok_or_else(invalid)?;
+    if hours.is_empty() || hours.len() > 2 || minutes.len() != 2 {
+        return Err(invalid());
+    }
+    let hours: u16 = hours.parse().map_err(|_| invalid())?;
+    let minutes: u16 = minutes.parse().map_err(|_| invalid())?;
+    if minutes >= 60 || hours * 60 + minutes > MINUTES_PER_DAY {
+        return Err(invalid());
+    }
+    Ok(hours * 60 + minutes)
+}
+
+fn format_time(minutes: u16) -> String {
+    let minutes = if minutes > MINUTES_PER_DAY {
+        minutes - MINUTES_PER_DAY
+    } else {
+        minutes
+    };
+    format!("{:02}:{:02}", minutes / 60, minutes % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MONDAY: usize = 0;
+    const SATURDAY: usize = 5;
+    const SUNDAY: usize = 6;
+
+    fn at(hours: u16, minutes: u16) -> u16 {
+        hours * 60 + minutes
+    }
+
+    #[test]
+    fn normalization_round_trips() {
+        for (src, canonical) in [
+            ("24/7", "24/7"),
+            (
+                "mo-fr 8:00-17:00; sa 10:00-14:00",
+                "Mo-Fr 08:00-17:00; Sa 10:00-14:00; Su off",
+            ),
+            (
+                "Mo-Su 09:00-12:00,13:00-17:00; Tu off",
+                "Mo 09:00-12:00,13:00-17:00; Tu off; We-Su 09:00-12:00,13:00-17:00",
+            ),
+            (
+                "Mo-Fr 06:00-22:00; Sa,Su 07:00-01:00; PH off",
+                "Mo-Fr 06:00-22:00; Sa,Su 07:00-01:00",
+            ),
+        ] {
+            let hours = OpeningHours::parse(src).unwrap();
+            assert_eq!(hours.to_string(), canonical, "{}", src);
+            // the canonical form parses back to the same week and prints the same again
+            let again = OpeningHours::parse(canonical).unwrap();
+            assert_eq!(again, hours, "{}", canonical);
+            assert_eq!(again.to_string(), canonical);
+        }
+    }
+
+    #[test]
+    fn span_past_midnight_is_open_the_next_morning() {
+        let hours = OpeningHours::parse("Mo-Fr 06:00-22:00; Sa 07:00-02:00; Su off").unwrap();
+        // saturday night into sunday, although sunday itself is off
+        assert!(hours.is_open(SATURDAY, at(23, 30)));
+        assert!(hours.is_open(SUNDAY, at(1, 0)));
+        assert!(!hours.is_open(SUNDAY, at(2, 0)));
+        // friday's span ends at 22:00 and doesn't spill into saturday
+        assert!(!hours.is_open(SATURDAY, at(1, 0)));
+        assert!(hours.is_open(MONDAY, at(6, 0)));
+        assert!(!hours.is_open(MONDAY, at(22, 0)));
+    }
+
+    #[test]
+    fn unsupported_syntax_is_rejected() {
+        for src in [
+            "",
+            "Jan-Mar Mo-Fr 08:00-17:00",
+            "Mo-Fr sunrise-sunset",
+            "Mo-Fr 08:00+",
+            "Mo-Fr 08:00-17:00 \"by appointment\"",
+            "Xy 08:00-17:00",
+            "Mo 25:00-26:00",
+            "Mo 8-17",
+        ] {
+            assert!(OpeningHours::parse(src).is_err(), "{:?} was accepted", src);
+        }
+    }
+}
@@ -0,0 +1,148 @@
+// place=* nodes, used to fill in localities that addresses and pois don't tag themselves
+use crate::geometry::{self, Coord};
+use rstar::{PointDistance, RTree, RTreeObject, AABB};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// which field a place kind can fill and how far out it still counts, a city reaches much
+// further than a hamlet, so a hamlet only wins when it's a lot closer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Locality {
+    City,
+    Suburb,
+    Place,
+}
+
+fn place_reach(kind: &str) -> Option<(Locality, f64)> {
+    Some(match kind {
+        "city" => (Locality::City, 25_000.0),
+        "town" => (Locality::City, 10_000.0),
+        "village" => (Locality::City, 5_000.0),
+        "suburb" => (Locality::Suburb, 3_000.0),
+        "neighbourhood" => (Locality::Suburb, 1_500.0),
+        "hamlet" => (Locality::Place, 2_000.0),
+        _ => return None,
+    })
+}
+
+pub const MAX_REACH_M: f64 = 25_000.0;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Place {
+    pub id: i64,
+    pub name: String,
+    pub place: String,
+    pub population: Option<i64>,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+// a named place node of one of the kinds above
+pub fn place_from_tags(
+    id: i64,
+    (lat, lon): Coord,
+    tags: &HashMap<String, String>,
+) -> Option<Place> {
+    let kind = tags.get("place")?;
+    place_reach(kind)?;
+    let name = tags.get("name").filter(|n| !n.trim().is_empty())?;
+
+    Some(Place {
+        id,
+        name: name.clone(),
+        place: kind.clone(),
+        // population is free text, "147,829" and "about 500" both show up
+        population: tags
+            .get("population")
+            .and_then(|p| p.replace([',', ' '], "").parse().ok()),
+        latitude: lat,
+        longitude: lon,
+    })
+}
+
+struct PlacePoint {
+    idx: usize,
+    point: [f64; 2],
+}
+
+impl RTreeObject for PlacePoint {
+    type Envelope = AABB<[f64; 2]>;
+
+    fn envelope(&self) -> Self::Envelope {
+        AABB::from_point(self.point)
+    }
+}
+
+impl PointDistance for PlacePoint {
+    fn distance_2(&self, point: &[f64; 2]) -> f64 {
+        let dx = self.point[0] - point[0];
+        let dy = self.point[1] - point[1];
+        dx * dx + dy * dy
+    }
+}
+
+pub struct PlaceIndex<'a> {
+    places: &'a [Place],
+    tree: RTree<PlacePoint>,
+}
+
+impl<'a> PlaceIndex<'a> {
+    pub fn new(places: &'a [Place]) -> PlaceIndex<'a> {
+        let points = places
+            .iter()
+            .enumerate()
+            .map(|(idx, p)| PlacePoint {
+                idx,
+                point: geometry::to_mercator((p.latitude, p.longitude)),
+            })
+            .collect();
+        PlaceIndex {
+            places,
+            tree: RTree::bulk_load(points),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.places.is_empty()
+    }
+
+    // the place that fills `locality` at this point, see best_fit
+    pub fn best(&self, lat: f64, lon: f64, locality: Locality) -> Option<&'a Place> {
+        let radius = MAX_REACH_M * geometry::mercator_scale(lat);
+        let candidates = self
+            .tree
+            .locate_within_distance(geometry::to_mercator((lat, lon)), radius * radius)
+            .map(|candidate| &self.places[candidate.idx]);
+        best_fit(candidates, lat, lon, locality)
+    }
+}
+
+// of the `candidates`, the place that fills `locality` at this point: the one with the
+// smallest distance relative to its reach, ties go to the lower node id
+pub fn best_fit<'p>(
+    candidates: impl IntoIterator<Item = &'p Place>,
+    lat: f64,
+    lon: f64,
+    locality: Locality,
+) -> Option<&'p Place> {
+    let mut best: Option<(f64, &Place)> = None;
+
+    for place in candidates {
+        let Some((kind_locality, reach)) = place_reach(&place.place) else {
+            continue;
+        };
+        if kind_locality != locality {
+            continue;
+        }
+
+        let distance = geometry::haversine_m((lat, lon), (place.latitude, place.longitude));
+        let score = distance / reach;
+        let better = best
+            .is_none_or(|(best_score, best_place)| (score, place.id) < (best_score, best_place.id));
+        if score <= 1.0 && better {
+            best = Some((score, place));
+        }
+    }
+
+    best.map(|(_, place)| place)
+}
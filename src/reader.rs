@@ -0,0 +1,57 @@
+// parallel pbf reading, each data blob is decoded and processed on the rayon pool
+use crate::progress;
+use osmpbf::{BlobDecode, BlobReader, PrimitiveBlock};
+use rayon::iter::{ParallelBridge, ParallelIterator};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+const PROGRESS_EVERY: u64 = 10_000_000;
+
+// runs `map_block` on every primitive block and combines the per-block results with
+// `reduce`, which must not depend on block order since blocks finish in any order
+pub fn par_map_blocks<T, I, M, R>(
+    path: &Path,
+    label: &str,
+    identity: I,
+    map_block: M,
+    reduce: R,
+) -> osmpbf::Result<T>
+where
+    T: Send,
+    I: Fn() -> T + Sync + Send,
+    M: Fn(&PrimitiveBlock) -> T + Sync + Send,
+    R: Fn(T, T) -> T + Sync + Send,
+{
+    let elements_seen = AtomicU64::new(0);
+
+    BlobReader::from_path(path)?
+        .par_bridge()
+        .map(|blob| match blob?.decode()? {
+            BlobDecode::OsmData(block) => {
+                let result = map_block(&block);
+                report_progress(&elements_seen, block_len(&block), label);
+                Ok(result)
+            }
+            BlobDecode::OsmHeader(_) | BlobDecode::Unknown(_) => Ok(identity()),
+        })
+        .try_reduce(&identity, |a, b| Ok(reduce(a, b)))
+}
+
+fn block_len(block: &PrimitiveBlock) -> u64 {
+    block
+        .groups()
+        .map(|g| g.nodes().len() + g.dense_nodes().len() + g.ways().len() + g.relations().len())
+        .sum::<usize>() as u64
+}
+
+fn report_progress(counter: &AtomicU64, added: u64, label: &str) {
+    let before = counter.fetch_add(added, Ordering::Relaxed);
+    let after = before + added;
+    if before / PROGRESS_EVERY != after / PROGRESS_EVERY {
+        progress!(
+            "  {} {}M elements...",
+            label,
+            after / PROGRESS_EVERY * PROGRESS_EVERY / 1_000_000
+        );
+    }
+}
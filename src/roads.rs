@@ -0,0 +1,96 @@
+// named highway ways, indexed segment by segment for nearest-street lookups
+use crate::geometry::{self, Coord};
+use rstar::{PointDistance, RTreeObject, AABB};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// roads that carry street addresses, motorways and paths don't
+const ADDRESS_ROADS: &[&str] = &[
+    "trunk",
+    "primary",
+    "secondary",
+    "tertiary",
+    "unclassified",
+    "residential",
+    "living_street",
+    "pedestrian",
+    "service",
+    "road",
+];
+
+// the street name of a way that addresses can be on
+pub fn road_name(tags: &HashMap<String, String>) -> Option<&str> {
+    let highway = tags.get("highway")?;
+    if !ADDRESS_ROADS.contains(&highway.as_str()) {
+        return None;
+    }
+    tags.get("name").map(|n| n.trim()).filter(|n| !n.is_empty())
+}
+
+// one piece of a road between two consecutive nodes, points are web mercator
+#[derive(Clone, Debug)]
+pub struct RoadSegment {
+    pub name: Arc<str>,
+    // the way and the position along it, a stable order for segments that are equally near
+    pub way_id: i64,
+    pub index: usize,
+    from: [f64; 2],
+    to: [f64; 2],
+}
+
+// splits a road way into segments that share one copy of its name
+pub fn road_segments(way_id: i64, name: &str, coords: &[Coord]) -> Vec<RoadSegment> {
+    let name: Arc<str> = Arc::from(name);
+    coords
+        .windows(2)
+        .enumerate()
+        .map(|(index, pair)| RoadSegment {
+            name: Arc::clone(&name),
+            way_id,
+            index,
+            from: geometry::to_mercator(pair[0]),
+            to: geometry::to_mercator(pair[1]),
+        })
+        .collect()
+}
+
+impl RoadSegment {
+    pub fn key(&self) -> (i64, usize) {
+        (self.way_id, self.index)
+    }
+
+    // the point on this segment closest to `(lat, lon)`
+    pub fn closest_point(&self, coord: Coord) -> Coord {
+        let [px, py] = geometry::to_mercator(coord);
+        let (x, y) = geometry::closest_on_segment(
+            px,
+            py,
+            self.from[0],
+            self.from[1],
+            self.to[0],
+            self.to[1],
+        );
+        geometry::from_mercator([x, y])
+    }
+}
+
+impl RTreeObject for RoadSegment {
+    type Envelope = AABB<[f64; 2]>;
+
+    fn envelope(&self) -> Self::Envelope {
+        AABB::from_corners(self.from, self.to)
+    }
+}
+
+impl PointDistance for RoadSegment {
+    fn distance_2(&self, point: &[f64; 2]) -> f64 {
+        geometry::segment_distance_sq(
+            point[0],
+            point[1],
+            self.from[0],
+            self.from[1],
+            self.to[0],
+            self.to[1],
+        )
+    }
+}
@@ -0,0 +1,323 @@
+// local http api over a generated database, responses are geojson shaped like photon's
+// https://github.com/komoot/photon so existing clients can point at it
+use crate::geocode::{AddressHit, Geocoder, LocalityNames, PoiHit, SearchHit, SearchQuery};
+use crate::{detail, progress};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+const DEFAULT_LIMIT: usize = 10;
+const MAX_LIMIT: usize = 100;
+const DEFAULT_REVERSE_RADIUS_M: f64 = 1_000.0;
+const DEFAULT_NEARBY_RADIUS_M: f64 = 500.0;
+const MAX_RADIUS_M: f64 = 50_000.0;
+
+struct HttpError {
+    status: u16,
+    message: String,
+}
+
+impl HttpError {
+    fn bad_request(message: impl Into<String>) -> HttpError {
+        HttpError {
+            status: 400,
+            message: message.into(),
+        }
+    }
+
+    fn not_found(message: impl Into<String>) -> HttpError {
+        HttpError {
+            status: 404,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for HttpError {
+    fn from(e: rusqlite::Error) -> HttpError {
+        HttpError {
+            status: 500,
+            message: format!("database error: {}", e),
+        }
+    }
+}
+
+// requests are answered one at a time, sqlite lookups take a few milliseconds at most
+pub fn serve(geocoder: &Geocoder, addr: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let server = Server::http(addr).map_err(|e| format!("Could not listen on {}: {}", addr, e))?;
+    progress!("Listening on http://{}", addr);
+    progress!("  /search?q=tim+hortons&lat=44.39&lon=-79.69&category=food&limit=10");
+    progress!("  /reverse?lat=44.39&lon=-79.69&radius=1000");
+    progress!("  /poi/node/123");
+    progress!("  /nearby?lat=44.39&lon=-79.69&radius=500&category=food&limit=10");
+
+    for request in server.incoming_requests() {
+        let (status, body) = match handle(geocoder, &request) {
+            Ok(body) => (200, body),
+            Err(e) => (e.status, json!({ "error": e.message })),
+        };
+        detail!("{} {} {}", request.method(), request.url(), status);
+
+        let response = Response::from_string(body.to_string())
+            .with_status_code(status)
+            .with_header(
+                Header::from_bytes("Content-Type", "application/json; charset=utf-8").unwrap(),
+            );
+        if let Err(e) = request.respond(response) {
+            detail!("  could not send the response: {}", e);
+        }
+    }
+    Ok(())
+}
+
+fn handle(geocoder: &Geocoder, request: &Request) -> Result<Value, HttpError> {
+    if *request.method() != Method::Get {
+        return Err(HttpError {
+            status: 405,
+            message: "only GET requests are supported".to_string(),
+        });
+    }
+
+    let (path, query) = request.url().split_once('?').unwrap_or((request.url(), ""));
+    let params: HashMap<String, String> = form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect();
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+    match segments.as_slice() {
+        ["search"] => search(geocoder, &params),
+        ["reverse"] => reverse(geocoder, &params),
+        ["nearby"] => nearby(geocoder, &params),
+        ["poi", osm_type, id] => poi(geocoder, osm_type, id),
+        _ => Err(HttpError::not_found(format!("no endpoint at {}", path))),
+    }
+}
+
+fn search(geocoder: &Geocoder, params: &HashMap<String, String>) -> Result<Value, HttpError> {
+    let text = params
+        .get("q")
+        .ok_or_else(|| HttpError::bad_request("missing q"))?;
+    let near = optional_point(params)?;
+    let hits = geocoder
+        .search(&SearchQuery {
+            text,
+            near,
+            category: params.get("category").map(String::as_str),
+            limit: limit(params)?,
+        })
+        // anything that isn't the database failing is a problem with the query
+        .map_err(|e| match e.downcast::<rusqlite::Error>() {
+            Ok(e) => HttpError::from(*e),
+            Err(e) => HttpError::bad_request(e.to_string()),
+        })?;
+    Ok(feature_collection(hits.iter().map(search_feature)))
+}
+
+fn reverse(geocoder: &Geocoder, params: &HashMap<String, String>) -> Result<Value, HttpError> {
+    let (lat, lon) = point(params)?;
+    let radius = radius(params, DEFAULT_REVERSE_RADIUS_M)?;
+    let result = geocoder.reverse(lat, lon, radius)?;
+
+    // the poi first, then the address and the area around the point, like photon's
+    // closest-first order
+    let mut features = Vec::new();
+    if let Some(poi) = &result.poi {
+        features.push(poi_feature(poi, "poi", &result.locality));
+    }
+    if let Some(address) = &result.address {
+        features.push(address_feature(address, &result.locality));
+    }
+    if let Some(area) = &result.area {
+        features.push(poi_feature(area, "area", &result.locality));
+    }
+    Ok(feature_collection(features))
+}
+
+fn nearby(geocoder: &Geocoder, params: &HashMap<String, String>) -> Result<Value, HttpError> {
+    let (lat, lon) = point(params)?;
+    let hits = geocoder.nearby_pois(
+        lat,
+        lon,
+        radius(params, DEFAULT_NEARBY_RADIUS_M)?,
+        params.get("category").map(String::as_str),
+        limit(params)?,
+    )?;
+    Ok(feature_collection(
+        hits.iter()
+            .map(|p| poi_feature(p, "poi", &LocalityNames::default())),
+    ))
+}
+
+fn poi(geocoder: &Geocoder, osm_type: &str, id: &str) -> Result<Value, HttpError> {
+    // photon's N/W/R work as well as the full names
+    let osm_type = match osm_type {
+        "node" | "N" | "n" => "node",
+        "way" | "W" | "w" => "way",
+        "relation" | "R" | "r" => "relation",
+        _ => {
+            return Err(HttpError::bad_request(format!(
+                "unknown osm type {}",
+                osm_type
+            )))
+        }
+    };
+    let id: i64 = id
+        .parse()
+        .map_err(|_| HttpError::bad_request(format!("invalid id {}", id)))?;
+
+    let mut feature = geocoder
+        .poi(osm_type, id)?
+        .map(|p| poi_feature(&p, "poi", &LocalityNames::default()))
+        .ok_or_else(|| HttpError::not_found(format!("no POI {}/{}", osm_type, id)))?;
+    // there's nothing to be distant from
+    if let Some(properties) = feature["properties"].as_object_mut() {
+        properties.remove("distance_m");
+    }
+    Ok(feature)
+}
+
+fn point(params: &HashMap<String, String>) -> Result<(f64, f64), HttpError> {
+    optional_point(params)?.ok_or_else(|| HttpError::bad_request("missing lat and lon"))
+}
+
+fn optional_point(params: &HashMap<String, String>) -> Result<Option<(f64, f64)>, HttpError> {
+    let (Some(lat), Some(lon)) = (params.get("lat"), params.get("lon")) else {
+        return Ok(None);
+    };
+    let lat: f64 = lat
+        .parse()
+        .map_err(|_| HttpError::bad_request(format!("invalid lat {}", lat)))?;
+    let lon: f64 = lon
+        .parse()
+        .map_err(|_| HttpError::bad_request(format!("invalid lon {}", lon)))?;
+    if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+        return Err(HttpError::bad_request("lat or lon out of range"));
+    }
+    Ok(Some((lat, lon)))
+}
+
+fn limit(params: &HashMap<String, String>) -> Result<usize, HttpError> {
+    match params.get("limit") {
+        Some(limit) => limit
+            .parse::<usize>()
+            .ok()
+            .filter(|n| (1..=MAX_LIMIT).contains(n))
+            .ok_or_else(|| HttpError::bad_request(format!("limit must be 1 to {}", MAX_LIMIT))),
+        None => Ok(DEFAULT_LIMIT),
+    }
+}
+
+fn radius(params: &HashMap<String, String>, default: f64) -> Result<f64, HttpError> {
+    match params.get("radius") {
+        Some(radius) => radius
+            .parse::<f64>()
+            .ok()
+            .filter(|r| *r > 0.0 && *r <= MAX_RADIUS_M)
+            .ok_or_else(|| {
+                HttpError::bad_request(format!("radius must be up to {} metres", MAX_RADIUS_M))
+            }),
+        None => Ok(default),
+    }
+}
+
+fn feature_collection(features: impl IntoIterator<Item = Value>) -> Value {
+    json!({
+        "type": "FeatureCollection",
+        "features": features.into_iter().collect::<Vec<_>>(),
+    })
+}
+
+fn feature(lat: f64, lon: f64, properties: Map<String, Value>) -> Value {
+    json!({
+        "type": "Feature",
+        "geometry": { "type": "Point", "coordinates": [lon, lat] },
+        "properties": properties,
+    })
+}
+
+// photon's one-letter osm_type
+fn osm_type_letter(osm_type: &str) -> &'static str {
+    match osm_type {
+        "node" => "N",
+        "way" => "W",
+        _ => "R",
+    }
+}
+
+// empty names are left out rather than sent as ""
+fn insert_text(properties: &mut Map<String, Value>, key: &str, value: &str) {
+    if !value.is_empty() {
+        properties.insert(key.to_string(), Value::from(value));
+    }
+}
+
+// photon calls the province state and the suburb district
+fn insert_locality(properties: &mut Map<String, Value>, locality: &LocalityNames) {
+    insert_text(properties, "city", &locality.city);
+    insert_text(properties, "district", &locality.suburb);
+    insert_text(properties, "county", &locality.county);
+    insert_text(properties, "state", &locality.province);
+}
+
+fn poi_feature(poi: &PoiHit, kind: &str, locality: &LocalityNames) -> Value {
+    let mut properties = Map::new();
+    properties.insert("type".to_string(), Value::from(kind));
+    properties.insert(
+        "osm_type".to_string(),
+        Value::from(osm_type_letter(&poi.osm_type)),
+    );
+    properties.insert("osm_id".to_string(), Value::from(poi.id));
+    properties.insert("name".to_string(), Value::from(poi.name.as_str()));
+    properties.insert("category".to_string(), Value::from(poi.category.as_str()));
+    insert_text(&mut properties, "subcategory", &poi.subcategory);
+    insert_text(&mut properties, "full_address", &poi.full_address);
+    insert_locality(&mut properties, locality);
+    // the poi's own admin areas win over the ones found around the point
+    insert_text(&mut properties, "county", &poi.county);
+    insert_text(&mut properties, "state", &poi.province);
+    insert_text(&mut properties, "municipality", &poi.municipality);
+    properties.insert("distance_m".to_string(), Value::from(poi.distance_m));
+    feature(poi.latitude, poi.longitude, properties)
+}
+
+fn address_feature(address: &AddressHit, locality: &LocalityNames) -> Value {
+    let mut properties = Map::new();
+    properties.insert("type".to_string(), Value::from("house"));
+    properties.insert(
+        "osm_type".to_string(),
+        Value::from(osm_type_letter(&address.osm_type)),
+    );
+    properties.insert("osm_id".to_string(), Value::from(address.id));
+    insert_text(&mut properties, "housenumber", &address.housenumber);
+    insert_text(&mut properties, "street", &address.street);
+    insert_text(&mut properties, "postcode", &address.postcode);
+    insert_text(&mut properties, "full_address", &address.full_address);
+    insert_locality(&mut properties, locality);
+    insert_text(&mut properties, "city", &address.city);
+    insert_text(&mut properties, "county", &address.county);
+    insert_text(&mut properties, "state", &address.province);
+    insert_text(&mut properties, "municipality", &address.municipality);
+    properties.insert("distance_m".to_string(), Value::from(address.distance_m));
+    feature(address.latitude, address.longitude, properties)
+}
+
+fn search_feature(hit: &SearchHit) -> Value {
+    let mut properties = Map::new();
+    let kind = if hit.kind == "poi" { "poi" } else { "house" };
+    properties.insert("type".to_string(), Value::from(kind));
+    properties.insert(
+        "osm_type".to_string(),
+        Value::from(osm_type_letter(&hit.osm_type)),
+    );
+    properties.insert("osm_id".to_string(), Value::from(hit.id));
+    properties.insert("name".to_string(), Value::from(hit.name.as_str()));
+    if let Some(category) = &hit.category {
+        properties.insert("category".to_string(), Value::from(category.as_str()));
+    }
+    insert_text(&mut properties, "full_address", &hit.full_address);
+    if let Some(distance) = hit.distance_m {
+        properties.insert("distance_m".to_string(), Value::from(distance));
+    }
+    properties.insert("score".to_string(), Value::from(hit.score));
+    feature(hit.latitude, hit.longitude, properties)
+}
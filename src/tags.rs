@@ -0,0 +1,47 @@
+// which raw osm tags are kept in the tags column, set with --keep-tags
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Clone, Default)]
+pub struct TagFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl TagFilter {
+    // an empty include list keeps every key that isn't excluded
+    pub fn new(include: Vec<String>, exclude: Vec<String>) -> Result<TagFilter, String> {
+        for pattern in include.iter().chain(exclude.iter()) {
+            if pattern.trim().is_empty() {
+                return Err("tag keys must not be empty".to_string());
+            }
+            if pattern.trim_end_matches('*').contains('*') {
+                return Err(format!(
+                    "\"{}\": only a trailing * is supported, e.g. contact:*",
+                    pattern
+                ));
+            }
+        }
+        Ok(TagFilter { include, exclude })
+    }
+
+    pub fn keeps(&self, key: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| key_matches(p, key)))
+            && !self.exclude.iter().any(|p| key_matches(p, key))
+    }
+
+    // sorted so the json text is the same on every run
+    pub fn apply(&self, tags: &HashMap<String, String>) -> BTreeMap<String, String> {
+        tags.iter()
+            .filter(|(key, _)| self.keeps(key))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    }
+}
+
+// "name" matches only name, "name:*" matches name:en, name:fr and so on
+fn key_matches(pattern: &str, key: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => key.starts_with(prefix),
+        None => pattern == key,
+    }
+}